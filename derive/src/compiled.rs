@@ -0,0 +1,151 @@
+/*
+grammar! { ... } builds the parse table at compile time and embeds it as a CompiledGrammar,
+conflicts are compile errors unless #![allow_conflicts] lets shifts win over reductions
+*/
+
+use std::collections::HashMap;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Attribute, Error, LitStr, Token,
+};
+
+use ry_script_lr::{Automaton, Rule, TransitionAction};
+
+use super::rule::parse_rule;
+
+/* Name of the EOF terminal registered by ScriptRunner::new */
+const EOF: &str = "EOF";
+
+struct GrammarInput {
+    allow_conflicts: bool,
+    texts: Punctuated<LitStr, Token![,]>,
+}
+
+impl Parse for GrammarInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut allow_conflicts = false;
+        for attr in input.call(Attribute::parse_inner)? {
+            match attr.path().is_ident("allow_conflicts") {
+                true => attr
+                    .meta
+                    .require_path_only()
+                    .map(|_| allow_conflicts = true)?,
+                false => return Err(Error::new_spanned(attr, "Unknown grammar attribute")),
+            }
+        }
+        Ok(GrammarInput {
+            allow_conflicts,
+            texts: Punctuated::parse_terminated(input)?,
+        })
+    }
+}
+
+/* Every reduce/reduce conflict, and every shift/reduce conflict unless they are allowed */
+fn conflict_errors(
+    input: &GrammarInput,
+    automaton: &Automaton,
+    symbols: &[&str],
+    terminal_count: usize,
+) -> Option<Error> {
+    let texts: Vec<&LitStr> = input.texts.iter().collect();
+    let mut errors = vec![];
+    for (state, rules) in &automaton.reduce_conflicts {
+        let names: Vec<String> = rules.iter().map(|&r| texts[r - 1].value()).collect();
+        let message = format!(
+            "Reduce/reduce conflict in state {} between {}",
+            state,
+            names.join(", ")
+        );
+        errors.push(Error::new_spanned(texts[rules[0] - 1], message));
+    }
+    for &state in &automaton.conflicts {
+        let Some(rule) = automaton.default_reduce[state] else {
+            continue;
+        };
+        let shifted: Vec<&str> = automaton.rows[state]
+            .iter()
+            .filter(|&&(symbol, _)| symbol < terminal_count)
+            .map(|&(symbol, _)| symbols[symbol])
+            .collect();
+        if input.allow_conflicts || shifted.is_empty() {
+            continue;
+        }
+        let message = format!(
+            "Shift/reduce conflict in state {} between reducing {} and shifting {}, add #![allow_conflicts] to let shifts win",
+            state,
+            texts[rule - 1].value(),
+            shifted.join(", ")
+        );
+        errors.push(Error::new_spanned(texts[rule - 1], message));
+    }
+    errors.into_iter().reduce(|mut error, next| {
+        error.combine(next);
+        error
+    })
+}
+
+pub fn expand(input: TokenStream) -> Result<TokenStream, Error> {
+    let input: GrammarInput = syn::parse2(input)?;
+    let texts = &input.texts;
+    let rules = texts
+        .iter()
+        .map(parse_rule)
+        .collect::<Result<Vec<_>, _>>()?;
+    if rules.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "Grammar set does not have a starter grammar",
+        ));
+    }
+    // number the symbols as GrammarSet does at runtime
+    let (terminals, non_terminals) = ry_script_lr::symbol_names(&rules);
+    let symbols: Vec<&str> = terminals.iter().chain(&non_terminals).copied().collect();
+    let ids: HashMap<&str, usize> = symbols.iter().enumerate().map(|(id, &s)| (s, id)).collect();
+    let numbered: Vec<Rule> = rules
+        .iter()
+        .map(|rule| Rule {
+            lval: ids[rule.lval.as_str()],
+            rvals: rule.rvals.iter().map(|s| ids[s.symbol.as_str()]).collect(),
+        })
+        .collect();
+    let eof = ids.get(EOF).copied();
+    let automaton = ry_script_lr::lr0(&numbered, symbols.len(), terminals.len(), eof);
+    if let Some(error) = conflict_errors(&input, &automaton, &symbols, terminals.len()) {
+        return Err(error);
+    }
+    let compressed = ry_script_lr::compress(&automaton.rows);
+
+    let slots = compressed.slots.iter().map(|slot| match slot {
+        Some((state, action)) => {
+            let action = match *action {
+                TransitionAction::Shift(s) => quote! { Shift(#s) },
+                TransitionAction::Reduce(r) => quote! { Reduce(#r) },
+                TransitionAction::Goto(s) => quote! { Goto(#s) },
+                TransitionAction::Accept => quote! { Accept },
+            };
+            quote! { ::std::option::Option::Some((#state, ::ry_script::lrparser::TransitionAction::#action)) }
+        }
+        None => quote! { ::std::option::Option::None },
+    });
+    let default_reduce = automaton.default_reduce.iter().map(|reduce| match reduce {
+        Some(rule) => quote! { ::std::option::Option::Some(#rule) },
+        None => quote! { ::std::option::Option::None },
+    });
+    let base = &compressed.base;
+    let conflicts = &automaton.conflicts;
+    let texts = texts.iter();
+    Ok(quote! {
+        ::ry_script::lrparser::CompiledGrammar {
+            rules: &[#(#texts),*],
+            symbols: &[#(#symbols),*],
+            base: &[#(#base),*],
+            slots: &[#(#slots),*],
+            default_reduce: &[#(#default_reduce),*],
+            conflicts: &[#(#conflicts),*],
+        }
+    })
+}
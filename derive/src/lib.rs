@@ -0,0 +1,44 @@
+mod compiled;
+mod rule;
+mod typed_ast;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+/* Generates `grammar_rules()` returning the GrammarRule list that reduces into the enum.
+ *
+ * #[derive(TypedAst)]
+ * #[ast(token = TokenType, start = "B -> S EOF")]
+ * #[rule("S -> A1")]                                 passthrough rule
+ * enum Expr {
+ *     #[rule("S -> id = A1")] Assign(Ident, Box<Expr>),
+ *     #[rule("Val -> int")] #[token(TokenType::Integer)] Int(Token<TokenType>),
+ * }
+ *
+ * Fields bind to labeled children when the rule has labels, declared with GrammarRule::labels, otherwise to every
+ * child when the counts match, otherwise to non-terminals and id/str/int/float.
+ * Box<_> and Self fields take reduced values, other fields are built From<Token>.
+ * #[token(pattern)] variants make up From<Token> for the enum, matched on the token
+ * type in order, they should cover every type or end with #[token(_)].
+ */
+#[proc_macro_derive(TypedAst, attributes(ast, rule, token))]
+pub fn derive_typed_ast(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    typed_ast::derive(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+/* Builds the parse table of the rule texts at compile time with the same LR(0) construction as
+ * LRParser::lr0. Conflicts are compile errors, #![allow_conflicts] first in the input lets shifts
+ * win over reductions as in a table built at runtime, reduce/reduce conflicts are always errors.
+ *
+ * static GRAMMAR: CompiledGrammar = grammar! { "B -> S EOF", "S -> id = int" };
+ * ScriptRunner::with_compiled_grammar(&GRAMMAR, rules, token_map, &operator, &keyword)
+ */
+#[proc_macro]
+pub fn grammar(input: TokenStream) -> TokenStream {
+    compiled::expand(input.into())
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
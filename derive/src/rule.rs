@@ -0,0 +1,32 @@
+/*
+Grammar rule texts in macro input, parsed with ry-script-lr as ry-script parses them
+*/
+
+use ry_script_lr::{RuleError, RuleText};
+use syn::{Error, LitStr};
+
+/* Terminals created by the lexer that carry a value, see ScriptRunner::new */
+pub const VALUE_TERMINALS: [&str; 4] = ["id", "str", "int", "float"];
+
+/* Children that carry data: labeled symbols, non-terminals and value terminals */
+pub fn significant(rule: &RuleText, non_terminals: &[String]) -> Vec<usize> {
+    rule.rvals
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| {
+            s.label.is_some()
+                || non_terminals.contains(&s.symbol)
+                || VALUE_TERMINALS.contains(&s.symbol.as_str())
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+pub fn parse_rule(text: &LitStr) -> Result<RuleText, Error> {
+    RuleText::parse(&text.value()).map_err(|error| match error {
+        RuleError::InvalidText => {
+            Error::new_spanned(text, format!("Invalid grammar {}", text.value()))
+        }
+        error => Error::new_spanned(text, error),
+    })
+}
@@ -0,0 +1,281 @@
+/*
+#[derive(TypedAst)] generates the GrammarRule list of a typed AST enum
+*/
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Ident, LitStr, Path, Type, Variant};
+
+use ry_script_lr::RuleText;
+
+use super::rule::{parse_rule, significant};
+
+struct VariantRule {
+    variant: Ident,
+    fields: Fields,
+    rule: RuleText,
+    span: LitStr,
+}
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream, Error> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "TypedAst does not support generic enums",
+        ));
+    }
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "TypedAst can only be derived for enums",
+            ))
+        }
+    };
+
+    // #[ast(token = T, start = "...")] and passthrough #[rule("...")] on the enum
+    let mut token: Option<Path> = None;
+    let mut start: Option<LitStr> = None;
+    let mut passthrough = vec![];
+    for attr in &input.attrs {
+        if attr.path().is_ident("ast") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("token") {
+                    token = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("start") {
+                    start = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `token` or `start`"))
+                }
+            })?;
+        } else if attr.path().is_ident("rule") {
+            let text: LitStr = attr.parse_args()?;
+            passthrough.push((parse_rule(&text)?, text));
+        }
+    }
+    let token = match token {
+        Some(token) => token,
+        None => return Err(Error::new_spanned(name, "missing #[ast(token = ...)]")),
+    };
+    let start = match start {
+        Some(start) => start,
+        None => return Err(Error::new_spanned(name, "missing #[ast(start = \"...\")]")),
+    };
+    let start_rule = parse_rule(&start)?;
+
+    let mut variant_rules = vec![];
+    let mut token_arms = vec![];
+    for variant in &data.variants {
+        for attr in variant.attrs.iter().filter(|a| a.path().is_ident("token")) {
+            let pattern = attr.meta.require_list()?.tokens.clone();
+            token_arms.push(token_arm(name, variant, pattern)?);
+        }
+        for attr in variant.attrs.iter().filter(|a| a.path().is_ident("rule")) {
+            let text: LitStr = attr.parse_args()?;
+            variant_rules.push(VariantRule {
+                variant: variant.ident.clone(),
+                fields: variant.fields.clone(),
+                rule: parse_rule(&text)?,
+                span: text,
+            });
+        }
+    }
+
+    let mut non_terminals = vec![start_rule.lval.clone()];
+    for rule in passthrough
+        .iter()
+        .map(|(r, _)| r)
+        .chain(variant_rules.iter().map(|v| &v.rule))
+    {
+        if !non_terminals.contains(&rule.lval) {
+            non_terminals.push(rule.lval.clone());
+        }
+    }
+
+    let mut rules = vec![quote! {
+        ::ry_script::runner::GrammarRule(#start, ::ry_script::ast::never_reducer)
+    }];
+    for (rule, text) in &passthrough {
+        let significant = significant(rule, &non_terminals);
+        let idx = match (rule.rvals.len(), significant.as_slice()) {
+            (1, _) => 0,
+            (_, [idx]) => *idx,
+            _ => {
+                return Err(Error::new_spanned(
+                    text,
+                    "a passthrough rule must have exactly one child that carries a value",
+                ))
+            }
+        };
+        rules.push(quote! {
+            ::ry_script::runner::GrammarRule(#text, |mut args| args.nth_val(#idx))
+        });
+    }
+    for rule in &variant_rules {
+        rules.push(variant_reducer(name, rule, &non_terminals)?);
+    }
+
+    // without #[token] variants the enum converts tokens itself
+    let from_token = match token_arms.is_empty() {
+        true => quote! {},
+        false => quote! {
+            impl ::std::convert::From<::ry_script::token::Token<#token>> for #name {
+                fn from(token: ::ry_script::token::Token<#token>) -> Self {
+                    match token.r#type {
+                        #(#token_arms)*
+                    }
+                }
+            }
+        },
+    };
+
+    Ok(quote! {
+        #from_token
+
+        impl #name {
+            pub fn grammar_rules<ENV, E: ::ry_script::error::RuntimeError>(
+            ) -> ::std::vec::Vec<::ry_script::runner::GrammarRule<ENV, #token, #name, E>> {
+                ::std::vec![#(#rules),*]
+            }
+        }
+    })
+}
+
+/* #[token(pattern)] builds the variant from tokens whose type matches, its one field is built From<Token> */
+fn token_arm(name: &Ident, variant: &Variant, pattern: TokenStream) -> Result<TokenStream, Error> {
+    let ident = &variant.ident;
+    let field = match variant.fields.iter().collect::<Vec<_>>().as_slice() {
+        [field] => *field,
+        _ => {
+            return Err(Error::new_spanned(
+                ident,
+                "a #[token] variant must have exactly one field",
+            ))
+        }
+    };
+    let value = quote! { ::std::convert::From::from(token) };
+    let construct = match &field.ident {
+        Some(field) => quote! { #name::#ident { #field: #value } },
+        None => quote! { #name::#ident(#value) },
+    };
+    Ok(quote! { #pattern => #construct, })
+}
+
+/* Binds every field of the variant to a child of the grammar rule */
+fn variant_reducer(
+    name: &Ident,
+    variant_rule: &VariantRule,
+    non_terminals: &[String],
+) -> Result<TokenStream, Error> {
+    let VariantRule {
+        variant,
+        fields,
+        rule,
+        span,
+    } = variant_rule;
+    let text = &rule.text;
+    let field_types: Vec<&Type> = fields.iter().map(|f| &f.ty).collect();
+
+    // take each bound child out of ReducerArg
+    let mut takes = vec![];
+    let mut labels = vec![];
+    if rule.has_labels() {
+        let bound: Vec<&String> = match fields {
+            Fields::Named(named) => named
+                .named
+                .iter()
+                .filter_map(|f| f.ident.as_ref())
+                .map(|field| {
+                    rule.rvals
+                        .iter()
+                        .filter_map(|s| s.label.as_ref())
+                        .find(|&label| field == label)
+                        .ok_or_else(|| {
+                            Error::new_spanned(span, format!("label {} is not defined", field))
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            _ => rule.rvals.iter().filter_map(|s| s.label.as_ref()).collect(),
+        };
+        if bound.len() != field_types.len() {
+            return Err(Error::new_spanned(
+                span,
+                format!(
+                    "{} has {} fields but the rule has {} labels",
+                    variant,
+                    field_types.len(),
+                    bound.len()
+                ),
+            ));
+        }
+        for (label, ty) in bound.iter().zip(&field_types) {
+            takes.push(convert(name, ty, quote! { args.get(#label)? }));
+            labels.push(label.as_str());
+        }
+    } else if !field_types.is_empty() {
+        let bound = match rule.rvals.len() == field_types.len() {
+            true => (0..rule.rvals.len()).collect(),
+            false => significant(rule, non_terminals),
+        };
+        if bound.len() != field_types.len() {
+            return Err(Error::new_spanned(
+                span,
+                format!(
+                    "{} has {} fields but the rule has {} children that carry a value",
+                    variant,
+                    field_types.len(),
+                    bound.len()
+                ),
+            ));
+        }
+        let mut cursor = 0;
+        for (idx, ty) in bound.iter().zip(&field_types) {
+            let n = idx - cursor;
+            cursor = idx + 1;
+            takes.push(convert(name, ty, quote! { args.nth_val(#n)? }));
+        }
+    }
+
+    let bindings: Vec<Ident> = (0..takes.len())
+        .map(|i| Ident::new(&format!("field{}", i), variant.span()))
+        .collect();
+    let construct = match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote! { #name::#variant { #(#names: #bindings),* } }
+        }
+        Fields::Unnamed(_) => quote! { #name::#variant(#(#bindings),*) },
+        Fields::Unit => quote! { #name::#variant },
+    };
+    let args = match takes.is_empty() {
+        true => quote! { _ },
+        false => quote! { mut args },
+    };
+    Ok(quote! {
+        ::ry_script::runner::GrammarRule(
+            #text,
+            |#args| {
+                #(let #bindings = #takes;)*
+                Ok(::ry_script::ast::ASTNode::Value(#construct))
+            },
+        )
+        .labels(&[#(#labels),*])
+    })
+}
+
+/* Box<_> and the enum itself are built from reduced values, anything else from a token */
+fn convert(name: &Ident, ty: &Type, node: TokenStream) -> TokenStream {
+    let last = match ty {
+        Type::Path(path) => path.path.segments.last().map(|s| &s.ident),
+        _ => None,
+    };
+    match last {
+        Some(ident) if ident == "Box" => quote! { ::std::boxed::Box::new(#node.into_value()?) },
+        Some(ident) if ident == name || ident == "Self" => quote! { #node.into_value()? },
+        _ => quote! { ::std::convert::From::from(#node.into_token()?) },
+    }
+}
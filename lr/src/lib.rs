@@ -0,0 +1,12 @@
+/*
+Rule text parsing and LR(0) table construction, shared by ry-script at runtime and grammar! at compile time
+so that both build the same table
+*/
+
+mod rule;
+mod table;
+
+pub use rule::{symbol_names, RuleError, RuleSymbol, RuleText};
+pub use table::{
+    compress, lr0, rules_by_lval, Automaton, Compressed, Item, Rule, TransitionAction,
+};
@@ -0,0 +1,88 @@
+/*
+Grammar rule text, written as "lval -> rval rval", a labeled rval is written as label:symbol
+*/
+
+use std::{collections::HashSet, fmt::Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    InvalidText,
+    DuplicateLabel(String),
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::InvalidText => write!(f, "Invalid grammar text"),
+            RuleError::DuplicateLabel(token) => write!(f, "Duplicate label {}", token),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSymbol {
+    pub label: Option<String>,
+    pub symbol: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleText {
+    pub text: String,
+    pub lval: String,
+    pub rvals: Vec<RuleSymbol>,
+}
+
+impl RuleText {
+    pub fn parse(text: &str) -> Result<RuleText, RuleError> {
+        let mut tokens = text.split(' ');
+        let lval = match tokens.next() {
+            Some(token) if !token.is_empty() => token.to_string(),
+            _ => return Err(RuleError::InvalidText),
+        };
+        match tokens.next() {
+            Some("->") => (),
+            _ => return Err(RuleError::InvalidText),
+        };
+        let mut rvals: Vec<RuleSymbol> = vec![];
+        for token in tokens {
+            let symbol = match token.split_once(':') {
+                Some((label, symbol)) if !label.is_empty() && !symbol.is_empty() => RuleSymbol {
+                    label: Some(label.to_string()),
+                    symbol: symbol.to_string(),
+                },
+                _ => RuleSymbol {
+                    label: None,
+                    symbol: token.to_string(),
+                },
+            };
+            if symbol.label.is_some() && rvals.iter().any(|s| s.label == symbol.label) {
+                return Err(RuleError::DuplicateLabel(token.to_string()));
+            }
+            rvals.push(symbol);
+        }
+        Ok(RuleText {
+            text: text.to_string(),
+            lval,
+            rvals,
+        })
+    }
+
+    pub fn has_labels(&self) -> bool {
+        self.rvals.iter().any(|s| s.label.is_some())
+    }
+}
+
+/* Symbol names in id order, terminals first in order of first use, then non-terminals in rule order */
+pub fn symbol_names(rules: &[RuleText]) -> (Vec<&str>, Vec<&str>) {
+    let mut seen = HashSet::new();
+    let lvals = rules.iter().map(|rule| rule.lval.as_str());
+    let non_terminals: Vec<&str> = lvals.filter(|&name| seen.insert(name)).collect();
+    let rvals = rules.iter().flat_map(|rule| &rule.rvals);
+    let terminals = rvals.map(|rval| rval.symbol.as_str());
+    (
+        terminals.filter(|&name| seen.insert(name)).collect(),
+        non_terminals,
+    )
+}
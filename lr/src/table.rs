@@ -0,0 +1,224 @@
+/*
+LR(0) automaton over numbered symbols, terminals are numbered before non-terminals
+*/
+
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt::Display,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransitionAction {
+    Shift(usize),
+    Reduce(usize),
+    Goto(usize),
+    Accept,
+}
+
+impl Display for TransitionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionAction::Shift(s) => write!(f, "shift {}", s)?,
+            TransitionAction::Reduce(r) => write!(f, "reduce {}", r)?,
+            TransitionAction::Goto(r) => write!(f, "goto {}", r)?,
+            TransitionAction::Accept => write!(f, "accept")?,
+        }
+        Ok(())
+    }
+}
+
+/* A rule as symbol ids, the rule number is one more than its index */
+pub struct Rule {
+    pub lval: usize,
+    pub rvals: Vec<usize>,
+}
+
+/* An LR(0) item interned as (rule index, dot position) */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Item {
+    pub rule_idx: usize,
+    pub rval_idx: usize,
+}
+
+impl Item {
+    pub fn current_symbol(&self, rules: &[Rule]) -> Option<usize> {
+        rules[self.rule_idx].rvals.get(self.rval_idx).copied()
+    }
+
+    pub fn advance(&self) -> Item {
+        Item {
+            rule_idx: self.rule_idx,
+            rval_idx: self.rval_idx + 1,
+        }
+    }
+
+    /* The core plus the items of every rule of a symbol right after a dot */
+    pub fn closure(core: &[Item], rules: &[Rule], rules_by_lval: &[Vec<usize>]) -> Vec<Item> {
+        let mut items = core.to_vec();
+        let mut seen: HashSet<Item> = items.iter().copied().collect();
+        let mut i = 0;
+        while let Some(item) = items.get(i) {
+            if let Some(symbol) = item.current_symbol(rules) {
+                for &rule_idx in &rules_by_lval[symbol] {
+                    let item = Item {
+                        rule_idx,
+                        rval_idx: 0,
+                    };
+                    if seen.insert(item) {
+                        items.push(item);
+                    }
+                }
+            }
+            i += 1;
+        }
+        items
+    }
+}
+
+pub fn rules_by_lval(rules: &[Rule], symbol_count: usize) -> Vec<Vec<usize>> {
+    let mut rules_by_lval = vec![vec![]; symbol_count];
+    for (rule_idx, rule) in rules.iter().enumerate() {
+        rules_by_lval[rule.lval].push(rule_idx);
+    }
+    rules_by_lval
+}
+
+/*
+Explicit actions of every state sorted by symbol id, the default reductions and the conflicting states,
+reduce_conflicts lists the states completing more than one rule with their rule numbers
+*/
+pub struct Automaton {
+    pub rows: Vec<Vec<(usize, TransitionAction)>>,
+    pub default_reduce: Vec<Option<usize>>,
+    pub conflicts: Vec<usize>,
+    pub reduce_conflicts: Vec<(usize, Vec<usize>)>,
+}
+
+/* The first rule is the start rule, a shift takes precedence over the default reduction of a state */
+pub fn lr0(
+    rules: &[Rule],
+    symbol_count: usize,
+    terminal_count: usize,
+    eof: Option<usize>,
+) -> Automaton {
+    let rules_by_lval = rules_by_lval(rules, symbol_count);
+    let starter_core = vec![Item {
+        rule_idx: 0,
+        rval_idx: 0,
+    }];
+    let mut state_ids: HashMap<Vec<Item>, usize> = HashMap::from([(starter_core.clone(), 0)]);
+    let mut cores = vec![starter_core];
+    let mut rows = Vec::new();
+    let mut default_reduce = Vec::new();
+    let mut conflicts = vec![];
+    let mut reduce_conflicts = vec![];
+    let mut state = 0;
+    while let Some(core) = cores.get(state) {
+        let items = Item::closure(core, rules, &rules_by_lval);
+        // a completed item reduces on every terminal, kept as the row default so that shifts take precedence
+        let mut transition_row = Vec::<(usize, TransitionAction)>::new();
+        let mut completed = vec![];
+        let mut transitions: Vec<(usize, Vec<Item>)> = vec![];
+        let mut transition_idx: HashMap<usize, usize> = HashMap::new();
+        for item in &items {
+            match item.current_symbol(rules) {
+                // EOF - Accept
+                Some(symbol) if Some(symbol) == eof => {
+                    transition_row.push((symbol, TransitionAction::Accept))
+                }
+                Some(symbol) => match transition_idx.entry(symbol) {
+                    Entry::Occupied(o) => transitions[*o.get()].1.push(item.advance()),
+                    Entry::Vacant(v) => {
+                        transitions.push((symbol, vec![item.advance()]));
+                        v.insert(transitions.len() - 1);
+                    }
+                },
+                None => completed.push(item.rule_idx + 1),
+            }
+        }
+        // find or add the target state of every shift/goto by its sorted core
+        for (symbol, mut core) in transitions {
+            core.sort_unstable();
+            core.dedup();
+            let next_state = match state_ids.entry(core) {
+                Entry::Occupied(o) => *o.get(),
+                Entry::Vacant(v) => {
+                    cores.push(v.key().clone());
+                    *v.insert(cores.len() - 1)
+                }
+            };
+            let action = match symbol < terminal_count {
+                true => TransitionAction::Shift(next_state),
+                false => TransitionAction::Goto(next_state),
+            };
+            transition_row.push((symbol, action));
+        }
+        transition_row.sort_unstable_by_key(|&(symbol, _)| symbol);
+        transition_row.dedup_by_key(|&mut (symbol, _)| symbol);
+        let shifts_terminal = transition_row
+            .first()
+            .is_some_and(|&(symbol, _)| symbol < terminal_count);
+        let reduce = completed.last().copied();
+        if completed.len() > 1 || (reduce.is_some() && shifts_terminal) {
+            conflicts.push(state);
+        }
+        if completed.len() > 1 {
+            reduce_conflicts.push((state, completed));
+        }
+        rows.push(transition_row);
+        default_reduce.push(reduce);
+        state += 1;
+    }
+    Automaton {
+        rows,
+        default_reduce,
+        conflicts,
+        reduce_conflicts,
+    }
+}
+
+/*
+Row displacement compressed rows, the action of (state, symbol) lives in slot base[state] + symbol
+when the slot is owned by that state
+*/
+pub struct Compressed {
+    pub base: Vec<usize>,
+    pub slots: Vec<Option<(usize, TransitionAction)>>,
+}
+
+/* Rows must be sorted by symbol id */
+pub fn compress(rows: &[Vec<(usize, TransitionAction)>]) -> Compressed {
+    // place the longest rows first, each at the first displacement where it fits
+    let mut order: Vec<usize> = (0..rows.len()).collect();
+    order.sort_by_key(|&state| std::cmp::Reverse(rows[state].len()));
+    let mut base = vec![0; rows.len()];
+    let mut slots: Vec<Option<(usize, TransitionAction)>> = vec![];
+    let mut first_free: usize = 0;
+    for state in order {
+        let row = &rows[state];
+        let Some(&(first_symbol, _)) = row.first() else {
+            continue;
+        };
+        let is_free = |slot: usize| slots.get(slot).is_none_or(Option::is_none);
+        let mut displacement = first_free.saturating_sub(first_symbol);
+        while !row
+            .iter()
+            .all(|&(symbol, _)| is_free(displacement + symbol))
+        {
+            displacement += 1;
+        }
+        let (last_symbol, _) = row[row.len() - 1];
+        if slots.len() <= displacement + last_symbol {
+            slots.resize(displacement + last_symbol + 1, None);
+        }
+        for &(symbol, action) in row {
+            slots[displacement + symbol] = Some((state, action));
+        }
+        base[state] = displacement;
+        while slots.get(first_free).is_some_and(Option::is_some) {
+            first_free += 1;
+        }
+    }
+    Compressed { base, slots }
+}
@@ -4,22 +4,29 @@ Abstract Syntax Tree
 
 use std::fmt::{Debug, Display};
 
-use super::error::RuntimeError;
+use super::error::{ParseError, ReducerError, Result, RuntimeError, TraceFrame};
+use super::limits;
 use super::runner::ReducerArg;
 use super::token::{ParserToken, Token};
 
 pub type ExpressionReducer<ENV, T, R, E> =
-    fn(args: ReducerArg<ENV, T, R, E>) -> ASTNode<ENV, T, R, E>;
+    fn(args: ReducerArg<ENV, T, R, E>) -> Result<ASTNode<ENV, T, R, E>, E>;
+
+pub type Action<ENV, T, R, E> = Box<dyn FnMut(&mut ENV) -> Result<ASTNode<ENV, T, R, E>, E>>;
+
+/* Receives the arguments of a Reduction with every expression among them already evaluated */
+pub type Continuation<ENV, T, R, E> =
+    Box<dyn FnOnce(&mut ENV, ReducerArg<ENV, T, R, E>) -> Result<ASTNode<ENV, T, R, E>, E>>;
 
 pub fn never_reducer<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError>(
     _: ReducerArg<ENV, T, R, E>,
-) -> ASTNode<ENV, T, R, E> {
-    panic!("Reach a reducer that should never be reached")
+) -> Result<ASTNode<ENV, T, R, E>, E> {
+    Err(ParseError::UnreachableReducer.into())
 }
 
 pub fn value_reducer<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError>(
     mut args: ReducerArg<ENV, T, R, E>,
-) -> ASTNode<ENV, T, R, E> {
+) -> Result<ASTNode<ENV, T, R, E>, E> {
     args.val()
 }
 
@@ -27,19 +34,176 @@ pub trait RuntimeValue<T: ParserToken<T>>: Debug + Display + From<Token<T>> {}
 
 pub enum ASTNode<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
     Token(Token<T>),
-    ActionExpression(
+    /* The expression arguments its action captured in a ReducerArg are evaluated before the action runs */
+    ActionExpression(&'static str, Action<ENV, T, R, E>),
+    /* Evaluated by the engine without recursion, see ReducerArg::defer */
+    Reduction(
         &'static str,
-        Box<dyn FnMut(&mut ENV) -> Result<ASTNode<ENV, T, R, E>, E>>,
+        ReducerArg<ENV, T, R, E>,
+        Continuation<ENV, T, R, E>,
     ),
     Value(R),
 }
 
+/* A Reduction or an ActionExpression whose arguments are being evaluated */
+struct Pending<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    name: &'static str,
+    args: ReducerArg<ENV, T, R, E>,
+    then: Then<ENV, T, R, E>,
+    /* Argument slot the next evaluated expression is written back to */
+    slot: usize,
+    _depth: limits::DepthGuard,
+}
+
+enum Then<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    Continuation(Continuation<ENV, T, R, E>),
+    /* The action takes the evaluated arguments from the ReducerArg it captured, traced as the slot it was taken from */
+    Action(Action<ENV, T, R, E>, Option<TraceFrame>),
+}
+
+impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> Pending<ENV, T, R, E> {
+    fn trace_frame(&self) -> Option<TraceFrame> {
+        match &self.then {
+            Then::Continuation(_) => Some(self.args.trace_frame(self.name)),
+            Then::Action(_, frame) => *frame,
+        }
+    }
+}
+
 impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ASTNode<ENV, T, R, E> {
+    /* Walks nested expressions with an explicit work stack */
     pub fn evaluate(self, env: &mut ENV) -> Result<ASTNode<ENV, T, R, E>, E> {
+        self.evaluate_in(env, None)
+    }
+
+    /* owner is the ReducerArg the node was taken from, whose parse knows the arguments actions captured */
+    pub(crate) fn evaluate_in(
+        self,
+        env: &mut ENV,
+        owner: Option<&ReducerArg<ENV, T, R, E>>,
+    ) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let mut frames: Vec<Pending<ENV, T, R, E>> = vec![];
+        /* a runtime error propagates through every expression still waiting for its arguments */
+        self.evaluate_with(env, owner, &mut frames)
+            .map_err(|error| {
+                frames
+                    .iter()
+                    .rev()
+                    .filter_map(Pending::trace_frame)
+                    .fold(error, |error, frame| error.traced(frame))
+            })
+    }
+
+    fn evaluate_with(
+        self,
+        env: &mut ENV,
+        owner: Option<&ReducerArg<ENV, T, R, E>>,
+        frames: &mut Vec<Pending<ENV, T, R, E>>,
+    ) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let mut node = match self {
+            ASTNode::Token(token) => return Ok(ASTNode::Value(R::from(token))),
+            node => node,
+        };
+        loop {
+            let mut done = match node {
+                ASTNode::Reduction(name, args, then) => {
+                    limits::charge()?;
+                    frames.push(Pending {
+                        name,
+                        args,
+                        then: Then::Continuation(then),
+                        slot: 0,
+                        _depth: limits::descend()?,
+                    });
+                    None
+                }
+                ASTNode::ActionExpression(name, mut action) => {
+                    limits::charge()?;
+                    let parent = frames.last().map(|f| &f.args).or(owner);
+                    let frame = frames.last().map(|f| f.args.slot_frame(f.slot, name));
+                    match parent.and_then(|parent| parent.action_args(&action)) {
+                        Some(args) => {
+                            frames.push(Pending {
+                                name,
+                                args,
+                                then: Then::Action(action, frame),
+                                slot: 0,
+                                _depth: limits::descend()?,
+                            });
+                            None
+                        }
+                        None => {
+                            let _depth = limits::descend()?;
+                            Some(action(env).map_err(|error| match frame {
+                                Some(frame) => error.traced(frame),
+                                None => error,
+                            })?)
+                        }
+                    }
+                }
+                node => Some(node),
+            };
+            node = loop {
+                if let Some(result) = done.take() {
+                    match frames.last_mut() {
+                        Some(frame) => frame.args.fill(frame.slot, result),
+                        None => return Ok(result),
+                    }
+                }
+                let frame = frames.last_mut().expect("a pending reduction");
+                match frame.args.next_expression(frame.slot) {
+                    Some((slot, child)) => {
+                        frame.slot = slot;
+                        break child;
+                    }
+                    None => {
+                        let Pending {
+                            name,
+                            args,
+                            then,
+                            _depth,
+                            ..
+                        } = frames.pop().expect("a pending reduction");
+                        done = Some(match then {
+                            Then::Continuation(then) => {
+                                let frame = args.trace_frame(name);
+                                then(env, args).map_err(|error| error.traced(frame))?
+                            }
+                            Then::Action(mut action, frame) => {
+                                drop(args);
+                                action(env).map_err(|error| match frame {
+                                    Some(frame) => error.traced(frame),
+                                    None => error,
+                                })?
+                            }
+                        });
+                    }
+                }
+            };
+        }
+    }
+
+    /* Unwraps a reduced value, tokens are converted without evaluating anything */
+    pub fn into_value(self) -> std::result::Result<R, ReducerError> {
+        match self {
+            ASTNode::Value(value) => Ok(value),
+            ASTNode::Token(token) => Ok(R::from(token)),
+            node => Err(ReducerError::UnexpectedNode("value", node.kind())),
+        }
+    }
+
+    pub fn into_token(self) -> std::result::Result<Token<T>, ReducerError> {
+        match self {
+            ASTNode::Token(token) => Ok(token),
+            node => Err(ReducerError::UnexpectedNode("token", node.kind())),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
         match self {
-            ASTNode::ActionExpression(_, mut action) => action(env),
-            ASTNode::Token(token) => Ok(ASTNode::Value(R::from(token))),
-            _ => Ok(self),
+            ASTNode::Token(_) => "token",
+            ASTNode::ActionExpression(_, _) | ASTNode::Reduction(_, _, _) => "expression",
+            ASTNode::Value(_) => "value",
         }
     }
 }
@@ -50,7 +214,9 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> Display
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ASTNode::Token(token) => write!(f, "{:?}", token.r#type)?,
-            ASTNode::ActionExpression(name, _) => write!(f, "{:?}", name)?,
+            ASTNode::ActionExpression(name, _) | ASTNode::Reduction(name, _, _) => {
+                write!(f, "{:?}", name)?
+            }
             ASTNode::Value(val) => write!(f, "{}", val)?,
         }
         Ok(())
@@ -61,7 +227,9 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> Debug for ASTN
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ASTNode::Token(token) => write!(f, "{:?}", token.r#type)?,
-            ASTNode::ActionExpression(name, _) => write!(f, "{:?}", name)?,
+            ASTNode::ActionExpression(name, _) | ASTNode::Reduction(name, _, _) => {
+                write!(f, "{:?}", name)?
+            }
             ASTNode::Value(val) => write!(f, "{:?}", val)?,
         }
         Ok(())
@@ -0,0 +1,134 @@
+/*
+Which grammar rules and parse states the scripts run by a ScriptRunner exercised
+*/
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::lrparser::LRParser;
+use super::token::ParserToken;
+
+/* Counts that add up across runs, and across runners that share the same parser */
+pub struct Coverage {
+    /* By rule index, one less than the rule number */
+    reduces: Vec<AtomicUsize>,
+    visits: Vec<AtomicUsize>,
+}
+
+impl Coverage {
+    pub fn new<T: ParserToken<T>>(lr_parser: &LRParser<T>) -> Coverage {
+        let counters = |len| (0..len).map(|_| AtomicUsize::new(0)).collect();
+        Coverage {
+            reduces: counters(lr_parser.grammar_set.grammars.len()),
+            visits: counters(lr_parser.state_count()),
+        }
+    }
+
+    pub(crate) fn reduced(&self, rule_number: usize) {
+        if let Some(count) = rule_number
+            .checked_sub(1)
+            .and_then(|idx| self.reduces.get(idx))
+        {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn entered(&self, state: usize) {
+        if let Some(count) = self.visits.get(state) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn reduces(&self, rule_number: usize) -> usize {
+        let count = rule_number
+            .checked_sub(1)
+            .and_then(|idx| self.reduces.get(idx));
+        count.map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    pub fn visits(&self, state: usize) -> usize {
+        let count = self.visits.get(state);
+        count.map_or(0, |count| count.load(Ordering::Relaxed))
+    }
+
+    pub fn reset(&self) {
+        for count in self.reduces.iter().chain(&self.visits) {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /* lr_parser names the rules, it should be the one the counts were recorded with */
+    pub fn report<T: ParserToken<T>>(&self, lr_parser: &LRParser<T>) -> CoverageReport {
+        let load = |counts: &[AtomicUsize]| -> Vec<usize> {
+            counts
+                .iter()
+                .map(|count| count.load(Ordering::Relaxed))
+                .collect()
+        };
+        let reduces = load(&self.reduces);
+        let visits = load(&self.visits);
+        /* the start rule is accepted on eof instead of reduced */
+        let unreduced = lr_parser
+            .grammar_set
+            .grammars
+            .iter()
+            .skip(1)
+            .filter(|grammar| reduces.get(grammar.rule_number - 1) == Some(&0))
+            .map(|grammar| {
+                (
+                    grammar.rule_number,
+                    grammar.to_string().trim_end().to_string(),
+                )
+            })
+            .collect();
+        let unvisited = (0..visits.len()).filter(|&state| visits[state] == 0);
+        CoverageReport {
+            unreduced,
+            unvisited: unvisited.collect(),
+            reduces,
+            visits,
+        }
+    }
+}
+
+pub struct CoverageReport {
+    /* Reduce count by rule index and visit count by state */
+    pub reduces: Vec<usize>,
+    pub visits: Vec<usize>,
+    /* Rule numbers with the rule text, leaving out the start rule */
+    pub unreduced: Vec<(usize, String)>,
+    pub unvisited: Vec<usize>,
+}
+
+impl CoverageReport {
+    pub fn is_complete(&self) -> bool {
+        self.unreduced.is_empty() && self.unvisited.is_empty()
+    }
+}
+
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let covered = |total: usize, missed: usize| {
+            let percent = match total {
+                0 => 100.0,
+                _ => (total - missed) as f64 * 100.0 / total as f64,
+            };
+            format!("{}/{} ({:.1}%)", total - missed, total, percent)
+        };
+        let rules = covered(self.reduces.len().saturating_sub(1), self.unreduced.len());
+        let states = covered(self.visits.len(), self.unvisited.len());
+        writeln!(f, "rules reduced: {}", rules)?;
+        writeln!(f, "states entered: {}", states)?;
+        if !self.unreduced.is_empty() {
+            writeln!(f, "never reduced:")?;
+            for (rule_number, rule) in &self.unreduced {
+                writeln!(f, "  {}. {}", rule_number, rule)?;
+            }
+        }
+        if !self.unvisited.is_empty() {
+            let states: Vec<_> = self.unvisited.iter().map(usize::to_string).collect();
+            writeln!(f, "never entered: {}", states.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+/*
+Step-through debugging of ActionExpression and Reduction evaluation
+*/
+
+use std::{
+    cell::{Cell, Ref, RefCell, RefMut},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use super::ast::{ASTNode, Action, Continuation, RuntimeValue};
+use super::error::RuntimeError;
+use super::token::{ParserToken, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /* Expressions reduced by the rule with this number */
+    Rule(usize),
+    /* Expressions whose source starts on this line, counted from 1 */
+    Line(usize),
+    Name(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    /* Pause again before the next expression */
+    Step,
+    /* Run until the next breakpoint */
+    Continue,
+}
+
+/* The expression about to be evaluated */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub name: &'static str,
+    pub rule: usize,
+    pub span: Span,
+    pub line: usize,
+}
+
+impl Frame {
+    fn hits(&self, breakpoint: &Breakpoint) -> bool {
+        match *breakpoint {
+            Breakpoint::Rule(rule) => self.rule == rule,
+            Breakpoint::Line(line) => self.line == line,
+            Breakpoint::Name(name) => self.name == name,
+        }
+    }
+}
+
+pub trait Debugger<ENV> {
+    fn pause(&mut self, frame: &Frame, env: &mut ENV) -> DebugCommand;
+}
+
+struct SessionState<D> {
+    debugger: D,
+    breakpoints: Vec<Breakpoint>,
+    stepping: bool,
+    line_starts: Vec<usize>,
+    /* Wrapped actions by address, the flag is cleared when the wrapper is dropped */
+    wrappers: HashMap<*const (), Rc<Cell<bool>>>,
+}
+
+impl<D> SessionState<D> {
+    fn before<ENV>(&mut self, frame: &Frame, env: &mut ENV)
+    where
+        D: Debugger<ENV>,
+    {
+        if self.stepping || self.breakpoints.iter().any(|b| frame.hits(b)) {
+            self.stepping = self.debugger.pause(frame, env) == DebugCommand::Step;
+        }
+    }
+}
+
+/* Clears the alive flag of a wrapper when the wrapper is dropped */
+struct DropFlag(Rc<Cell<bool>>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+pub struct DebugSession<D>(Rc<RefCell<SessionState<D>>>);
+
+impl<D> DebugSession<D> {
+    /* Runs until a breakpoint unless step is called */
+    pub fn new(debugger: D) -> DebugSession<D> {
+        DebugSession(Rc::new(RefCell::new(SessionState {
+            debugger,
+            breakpoints: vec![],
+            stepping: false,
+            line_starts: vec![],
+            wrappers: HashMap::new(),
+        })))
+    }
+
+    /* Pauses before the first expression */
+    pub fn step(self) -> DebugSession<D> {
+        self.0.borrow_mut().stepping = true;
+        self
+    }
+
+    pub fn breakpoint(self, breakpoint: Breakpoint) -> DebugSession<D> {
+        self.0.borrow_mut().breakpoints.push(breakpoint);
+        self
+    }
+
+    pub fn debugger(&self) -> Ref<'_, D> {
+        Ref::map(self.0.borrow(), |state| &state.debugger)
+    }
+
+    pub fn debugger_mut(&self) -> RefMut<'_, D> {
+        RefMut::map(self.0.borrow_mut(), |state| &mut state.debugger)
+    }
+
+    pub(crate) fn start(&self, source: &str) {
+        let mut state = self.0.borrow_mut();
+        state.line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        state.wrappers.clear();
+    }
+
+    /* Wraps a freshly reduced expression so that it pauses before evaluation, passed through expressions are already wrapped */
+    pub(crate) fn instrument<ENV, T, R, E>(
+        &self,
+        node: ASTNode<ENV, T, R, E>,
+        rule: usize,
+        span: Span,
+    ) -> ASTNode<ENV, T, R, E>
+    where
+        ENV: 'static,
+        T: ParserToken<T> + 'static,
+        R: RuntimeValue<T> + 'static,
+        E: RuntimeError + 'static,
+        D: Debugger<ENV> + 'static,
+    {
+        let (name, original) = match &node {
+            ASTNode::ActionExpression(name, action) => (*name, address(&**action)),
+            ASTNode::Reduction(name, _, then) => (*name, address(&**then)),
+            _ => return node,
+        };
+        let mut state = self.0.borrow_mut();
+        let alive = state.wrappers.get(&original);
+        if alive.is_some_and(|alive| alive.get()) {
+            return node;
+        }
+        let frame = Frame {
+            name,
+            rule,
+            span,
+            line: state.line_starts.partition_point(|&s| s <= span.start),
+        };
+        let alive = Rc::new(Cell::new(true));
+        let flag = DropFlag(Rc::clone(&alive));
+        let session = Rc::clone(&self.0);
+        let (wrapped, node) = match node {
+            ASTNode::ActionExpression(name, mut action) => {
+                let wrapper: Action<ENV, T, R, E> = Box::new(move |env| {
+                    let _flag = &flag;
+                    session.borrow_mut().before(&frame, env);
+                    action(env)
+                });
+                (address(&*wrapper), ASTNode::ActionExpression(name, wrapper))
+            }
+            ASTNode::Reduction(name, args, then) => {
+                /* a Reduction pauses once its arguments have been evaluated */
+                let wrapper: Continuation<ENV, T, R, E> = Box::new(move |env, args| {
+                    let _flag = &flag;
+                    session.borrow_mut().before(&frame, env);
+                    then(env, args)
+                });
+                (address(&*wrapper), ASTNode::Reduction(name, args, wrapper))
+            }
+            node => return node,
+        };
+        state.wrappers.insert(wrapped, alive);
+        node
+    }
+}
+
+fn address<F: ?Sized>(action: &F) -> *const () {
+    action as *const F as *const ()
+}
@@ -0,0 +1,463 @@
+/*
+Renders errors against the source they came from
+*/
+
+use std::{cell::RefCell, fmt::Write};
+
+use super::error::{
+    GrammarError, LexerError, LimitError, ParseError, ReducerError, RuntimeError, ScriptError,
+    SyntaxError,
+};
+use super::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /* Primary labels point at the error itself, secondary ones at related source */
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    /* Stable identifier of the kind of error, see ScriptError::code */
+    pub code: Option<&'static str>,
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    /* Terminals the parser would have accepted, empty when not known */
+    pub expected: Vec<String>,
+}
+
+thread_local! {
+    /* Warnings of the run on this thread, None unless the run collects them */
+    static WARNINGS: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/* Reports a non-fatal diagnostic from a reducer or an expression, dropped when the run does not collect warnings */
+pub fn warn(diagnostic: Diagnostic) {
+    WARNINGS.with_borrow_mut(|warnings| {
+        if let Some(warnings) = warnings {
+            warnings.push(diagnostic);
+        }
+    });
+}
+
+/* Restores the warnings of an enclosing run when dropped */
+pub(crate) struct WarningSink(Option<Vec<Diagnostic>>);
+
+impl WarningSink {
+    pub(crate) fn collect() -> WarningSink {
+        WarningSink(WARNINGS.replace(Some(vec![])))
+    }
+
+    /* Restores the enclosing run now, so there is nothing left to restore when dropped */
+    pub(crate) fn finish(mut self) -> Vec<Diagnostic> {
+        let outer = self.0.take();
+        std::mem::forget(self);
+        WARNINGS.replace(outer).unwrap_or_default()
+    }
+}
+
+impl Drop for WarningSink {
+    fn drop(&mut self) {
+        WARNINGS.set(self.0.take());
+    }
+}
+
+/* Escape sequences around each part of the output, all empty for plain text */
+struct Style {
+    severity: &'static str,
+    message: &'static str,
+    gutter: &'static str,
+    help: &'static str,
+    reset: &'static str,
+}
+
+const PLAIN: Style = Style {
+    severity: "",
+    message: "",
+    gutter: "",
+    help: "",
+    reset: "",
+};
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            code: None,
+            severity,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            help: None,
+            expected: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn code(mut self, code: &'static str) -> Diagnostic {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    /* Lists the expected terminals and mentions them in the help text */
+    pub fn expected(mut self, expected: Vec<String>) -> Diagnostic {
+        self.help = match expected.as_slice() {
+            [] => self.help,
+            [one] => Some(format!("expected {}", one)),
+            many => Some(format!("expected one of {}", many.join(", "))),
+        };
+        self.expected = expected;
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(source, &PLAIN)
+    }
+
+    pub fn render_ansi(&self, source: &str) -> String {
+        let style = Style {
+            severity: match self.severity {
+                Severity::Error => "\x1b[1;31m",
+                Severity::Warning => "\x1b[1;33m",
+                Severity::Note => "\x1b[1;36m",
+            },
+            message: "\x1b[1m",
+            gutter: "\x1b[1;34m",
+            help: "\x1b[1;32m",
+            reset: "\x1b[0m",
+        };
+        self.render_with(source, &style)
+    }
+
+    fn render_with(&self, source: &str, style: &Style) -> String {
+        let lines = SourceLines::new(source);
+        /* wide enough for the last line any label touches */
+        let width = self
+            .labels
+            .iter()
+            .map(|label| lines.position(label.span.end).0 + 1)
+            .max()
+            .unwrap_or(1)
+            .to_string()
+            .len();
+        let Style {
+            severity,
+            message,
+            gutter,
+            help,
+            reset,
+        } = style;
+        let pad = " ".repeat(width);
+        let mut text = String::new();
+        let code = self
+            .code
+            .map(|code| format!("[{}]", code))
+            .unwrap_or_default();
+        let _ = writeln!(
+            text,
+            "{severity}{}{}{reset}{message}: {}{reset}",
+            self.severity, code, self.message
+        );
+        if let Some(first) = self.labels.iter().find(|label| label.primary) {
+            let (line, column) = lines.position(first.span.start);
+            let _ = writeln!(text, "{pad}{gutter}-->{reset} {}:{}", line + 1, column + 1);
+            let _ = writeln!(text, "{pad} {gutter}|{reset}");
+        }
+        for label in &self.labels {
+            let (first, _) = lines.position(label.span.start);
+            let (last, _) = lines.position(label.span.end.max(label.span.start + 1) - 1);
+            for line in first..=last.max(first) {
+                let (start, end) = lines.bounds(line);
+                let from = label.span.start.clamp(start, end);
+                let to = label.span.end.clamp(start, end);
+                let offset = source[start..from].chars().count();
+                let carets = source[from..to].chars().count().max(1);
+                let (marker, colour) = match label.primary {
+                    true => ("^", severity),
+                    false => ("-", gutter),
+                };
+                let _ = writeln!(
+                    text,
+                    "{gutter}{:>width$} |{reset} {}",
+                    line + 1,
+                    &source[start..end]
+                );
+                let _ = write!(
+                    text,
+                    "{pad} {gutter}|{reset} {}{colour}{}",
+                    " ".repeat(offset),
+                    marker.repeat(carets)
+                );
+                match line == last && !label.message.is_empty() {
+                    true => {
+                        let _ = writeln!(text, " {}{reset}", label.message);
+                    }
+                    false => {
+                        let _ = writeln!(text, "{reset}");
+                    }
+                }
+            }
+        }
+        if !self.labels.is_empty() && (!self.notes.is_empty() || self.help.is_some()) {
+            let _ = writeln!(text, "{pad} {gutter}|{reset}");
+        }
+        for note in &self.notes {
+            let _ = writeln!(
+                text,
+                "{pad} {gutter}={reset} {message}note{reset}: {}",
+                note
+            );
+        }
+        if let Some(text_help) = &self.help {
+            let _ = writeln!(
+                text,
+                "{pad} {gutter}={reset} {help}help{reset}: {}",
+                text_help
+            );
+        }
+        text
+    }
+}
+
+/* Byte offsets of the start of every line */
+struct SourceLines<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> SourceLines<'a> {
+    fn new(source: &'a str) -> SourceLines<'a> {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceLines { source, starts }
+    }
+
+    /* Zero based line and character column of a byte offset, clamped to the source */
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = self.floor(offset);
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        let column = self.source[self.starts[line]..offset].chars().count();
+        (line, column)
+    }
+
+    /* Byte range of a line without its line break */
+    fn bounds(&self, line: usize) -> (usize, usize) {
+        let start = self.starts[line];
+        let end = self
+            .starts
+            .get(line + 1)
+            .map_or(self.source.len(), |&next| next - 1);
+        let end = match self.source[start..end].ends_with('\r') {
+            true => end - 1,
+            false => end,
+        };
+        (start, end)
+    }
+
+    fn floor(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/* Errors collected by Lexer::parse_recovering */
+impl From<&LexerError> for Diagnostic {
+    fn from(error: &LexerError) -> Diagnostic {
+        let diagnostic = match error {
+            LexerError::UnexpectedToken(ch, span) => {
+                Diagnostic::error(format!("unexpected character `{}`", ch.escape_debug()))
+                    .label(*span, "not the start of any token")
+            }
+            LexerError::UnterminatedString(span) => Diagnostic::error("unterminated string")
+                .label(*span, "the string starts here and never ends")
+                .help("close the string with `\"`"),
+            LexerError::Limit(error) => Diagnostic::error(format!("LimitError: {}", error)),
+            error => Diagnostic::error(format!("LexerError: {}", error)),
+        };
+        diagnostic.code(lexer_code(error))
+    }
+}
+
+impl<E: RuntimeError> From<&ScriptError<E>> for Diagnostic {
+    fn from(error: &ScriptError<E>) -> Diagnostic {
+        let diagnostic = match error {
+            ScriptError::Lexer(error) => return Diagnostic::from(error),
+            ScriptError::Syntax(SyntaxError::UnexpectedToken(found, span, expected)) => {
+                Diagnostic::error(format!("unexpected token {}", found))
+                    .label(*span, "unexpected here")
+                    .expected(expected.clone())
+            }
+            ScriptError::Syntax(SyntaxError::SyntaxError) => {
+                Diagnostic::error("unexpected end of input")
+                    .note("the input ended before the grammar could accept it")
+            }
+            ScriptError::Runtime(_, trace) if !trace.is_empty() => {
+                let diagnostic = Diagnostic::error(error.message())
+                    .label(trace[0].span, format!("in `{}`", trace[0].name));
+                trace[1..].iter().fold(diagnostic, |diagnostic, frame| {
+                    diagnostic.secondary(frame.span, format!("in `{}`", frame.name))
+                })
+            }
+            error => Diagnostic::error(error.message()),
+        };
+        diagnostic.code(error.code())
+    }
+}
+
+impl<E: RuntimeError> ScriptError<E> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::from(self)
+    }
+
+    /* Shortcut for rendering the diagnostic of the error as plain text */
+    pub fn render(&self, source: &str) -> String {
+        self.diagnostic().render(source)
+    }
+
+    /* Frames of a runtime error innermost first, as in `a * b` at 3:5, in `id = val` at 3:1 */
+    pub fn render_trace(&self, source: &str) -> String {
+        let lines = SourceLines::new(source);
+        self.trace()
+            .iter()
+            .map(|frame| {
+                let (line, column) = lines.position(frame.span.start);
+                format!("in `{}` at {}:{}", frame.name, line + 1, column + 1)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /* Stable code of the error kind, codes are never reused for a different kind */
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScriptError::Lexer(error) => lexer_code(error),
+            ScriptError::Grammar(error) => match error {
+                GrammarError::InvalidGrammarText(_, _) => "E0201",
+                GrammarError::InvalidSymbol(_, _) => "E0202",
+                GrammarError::DuplicateLabel(_, _) => "E0203",
+                GrammarError::InvalidParseTable(_) => "E0204",
+                GrammarError::StaleParseTable => "E0205",
+                GrammarError::MissingStartRule => "E0206",
+                GrammarError::CompiledGrammarMismatch => "E0207",
+            },
+            ScriptError::Parse(error) => match error {
+                ParseError::IncorrectParseResult => "E0301",
+                ParseError::UnexpectedSymbol(_) => "E0302",
+                ParseError::GrammarDoesNotExist(_) => "E0303",
+                ParseError::StateDoesNotExist(_) => "E0304",
+                ParseError::UnreachableReducer => "E0305",
+                ParseError::EmptyParseStack => "E0306",
+                ParseError::StackUnderflow(_) => "E0307",
+                ParseError::MissingGoto(_, _) => "E0308",
+                ParseError::UnexpectedGoto(_) => "E0309",
+                ParseError::UnbalancedStack(_) => "E0310",
+                ParseError::EditOutsideSource(_) => "E0311",
+            },
+            ScriptError::Syntax(error) => match error {
+                SyntaxError::SyntaxError => "E0400",
+                SyntaxError::UnexpectedToken(_, _, _) => "E0401",
+            },
+            ScriptError::Reducer(error) => match error {
+                ReducerError::MissingArgument(_, _) => "E0500",
+                ReducerError::UnexpectedNode(_, _) => "E0501",
+                ReducerError::UnknownLabel(_) => "E0502",
+            },
+            ScriptError::Runtime(_, _) => "E0600",
+            ScriptError::OutOfFuel => "E0700",
+            ScriptError::DeadlineExceeded => "E0701",
+            ScriptError::Cancelled => "E0702",
+            ScriptError::Limit(error) => limit_code(error),
+        }
+    }
+}
+
+fn lexer_code(error: &LexerError) -> &'static str {
+    match error {
+        LexerError::UnfinishedInput => "E0100",
+        LexerError::UnexpectedToken(_, _) => "E0101",
+        LexerError::EmptyInput => "E0102",
+        LexerError::UnterminatedString(_) => "E0103",
+        LexerError::Limit(error) => limit_code(error),
+    }
+}
+
+fn limit_code(error: &LimitError) -> &'static str {
+    match error {
+        LimitError::SourceTooLong(_) => "E0710",
+        LimitError::TooManyTokens(_) => "E0711",
+        LimitError::ParseStackTooDeep(_) => "E0712",
+        LimitError::EvaluationTooDeep(_) => "E0713",
+    }
+}
+
+/* Errors serialise as their diagnostic */
+#[cfg(feature = "serde")]
+impl<E: RuntimeError> serde::Serialize for ScriptError<E> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.diagnostic().serialize(serializer)
+    }
+}
@@ -1,27 +1,97 @@
+use super::token::Span;
+
 pub type Result<T, E> = std::result::Result<T, ScriptError<E>>;
 
 pub enum ScriptError<E> {
-    Runtime(E),
+    /* The error and the expressions it propagated through, innermost first */
+    Runtime(E, Vec<TraceFrame>),
     Grammar(GrammarError),
     Lexer(LexerError),
     Parse(ParseError),
     Syntax(SyntaxError),
+    Reducer(ReducerError),
+    OutOfFuel,
+    DeadlineExceeded,
+    Cancelled,
+    Limit(LimitError),
 }
 
 impl<E> From<E> for ScriptError<E> {
     fn from(error: E) -> Self {
-        ScriptError::Runtime(error)
+        ScriptError::Runtime(error, vec![])
     }
 }
 
+/* An expression a runtime error propagated through */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub name: &'static str,
+    pub rule: usize,
+    pub span: Span,
+}
+
+impl<E> ScriptError<E> {
+    /* Runtime errors only, other errors are not about a particular expression */
+    pub fn trace(&self) -> &[TraceFrame] {
+        match self {
+            ScriptError::Runtime(_, trace) => trace,
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn traced(mut self, frame: TraceFrame) -> Self {
+        if let ScriptError::Runtime(_, trace) = &mut self {
+            trace.push(frame);
+        }
+        self
+    }
+}
+
+/* Only the kind of error, the error it wraps is the source so that reporters print it once */
 impl<E: RuntimeError> std::fmt::Display for ScriptError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ScriptError::Grammar(error) => write!(f, "GrammarError: {}", error),
-            ScriptError::Lexer(error) => write!(f, "LexerError: {}", error),
-            ScriptError::Parse(error) => write!(f, "ParseError: {}", error),
-            ScriptError::Syntax(error) => write!(f, "{:?}", error),
-            ScriptError::Runtime(error) => write!(f, "RuntimeError: {}", error),
+            ScriptError::Grammar(_) => write!(f, "GrammarError"),
+            ScriptError::Lexer(_) => write!(f, "LexerError"),
+            ScriptError::Parse(_) => write!(f, "ParseError"),
+            ScriptError::Syntax(_) => write!(f, "SyntaxError"),
+            ScriptError::Reducer(_) => write!(f, "ReducerError"),
+            ScriptError::Runtime(error, _) => match error.as_error() {
+                Some(_) => write!(f, "RuntimeError"),
+                None => write!(f, "RuntimeError: {}", error),
+            },
+            ScriptError::OutOfFuel => write!(f, "Script ran out of fuel"),
+            ScriptError::DeadlineExceeded => write!(f, "Script exceeded its deadline"),
+            ScriptError::Cancelled => write!(f, "Script was cancelled"),
+            ScriptError::Limit(_) => write!(f, "LimitError"),
+        }
+    }
+}
+
+impl<E: RuntimeError> ScriptError<E> {
+    /* The error and its sources on one line, as in GrammarError: Invalid symbol nope in rule 2 */
+    pub fn message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(&format!(": {}", error));
+            source = error.source();
+        }
+        message
+    }
+}
+
+impl<E: RuntimeError> std::error::Error for ScriptError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScriptError::Runtime(error, _) => error.as_error(),
+            ScriptError::Grammar(error) => Some(error),
+            ScriptError::Lexer(error) => Some(error),
+            ScriptError::Parse(error) => Some(error),
+            ScriptError::Syntax(error) => Some(error),
+            ScriptError::Reducer(error) => Some(error),
+            ScriptError::Limit(error) => Some(error),
+            ScriptError::OutOfFuel | ScriptError::DeadlineExceeded | ScriptError::Cancelled => None,
         }
     }
 }
@@ -32,76 +102,191 @@ impl<E: RuntimeError> std::fmt::Debug for ScriptError<E> {
             ScriptError::Grammar(error) => write!(f, "GrammarError: {}", error),
             ScriptError::Lexer(error) => write!(f, "LexerError: {}", error),
             ScriptError::Parse(error) => write!(f, "ParseError: {}", error),
-            ScriptError::Syntax(error) => write!(f, "{:?}", error),
-            ScriptError::Runtime(error) => write!(f, "RuntimeError: {}", error),
+            ScriptError::Syntax(error) => write!(f, "SyntaxError: {}", error),
+            ScriptError::Reducer(error) => write!(f, "ReducerError: {}", error),
+            ScriptError::Runtime(error, _) => write!(f, "RuntimeError: {}", error),
+            ScriptError::OutOfFuel => write!(f, "Script ran out of fuel"),
+            ScriptError::DeadlineExceeded => write!(f, "Script exceeded its deadline"),
+            ScriptError::Cancelled => write!(f, "Script was cancelled"),
+            ScriptError::Limit(error) => write!(f, "LimitError: {}", error),
         }
     }
 }
 
+/* Rule payloads are rule numbers as in Grammar::rule_number, counted from 1 */
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GrammarError {
-    Error(&'static str),
-    InvalidGrammarText(&'static str),
-    InvalidSymbol(&'static str),
+    MissingStartRule,
+    CompiledGrammarMismatch,
+    /* Rule number and its text */
+    InvalidGrammarText(usize, String),
+    /* Rule number and the symbol that is not defined or cannot be used there */
+    InvalidSymbol(usize, String),
+    /* Rule number and the label used twice */
+    DuplicateLabel(usize, String),
+    InvalidParseTable(String),
+    StaleParseTable,
 }
 
 impl std::fmt::Display for GrammarError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            GrammarError::Error(msg) => write!(f, "{}", msg),
-            GrammarError::InvalidGrammarText(grammar) => write!(f, "Invalid grammar {}", grammar),
-            GrammarError::InvalidSymbol(symbol) => write!(f, "Invalid symbol {}", symbol),
+            GrammarError::MissingStartRule => {
+                write!(f, "Grammar set does not have a starter grammar")
+            }
+            GrammarError::CompiledGrammarMismatch => {
+                write!(f, "Grammar rules do not match the compiled grammar")
+            }
+            GrammarError::InvalidGrammarText(rule, text) => {
+                write!(f, "Invalid grammar rule {}: {}", rule, text)
+            }
+            GrammarError::InvalidSymbol(rule, symbol) => {
+                write!(f, "Invalid symbol {} in rule {}", symbol, rule)
+            }
+            GrammarError::DuplicateLabel(rule, label) => {
+                write!(f, "Duplicate label {} in rule {}", label, rule)
+            }
+            GrammarError::InvalidParseTable(msg) => write!(f, "Invalid parse table: {}", msg),
+            GrammarError::StaleParseTable => {
+                write!(f, "Parse table was built from a different grammar")
+            }
         }
     }
 }
 
+impl std::error::Error for GrammarError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LexerError {
-    Error(&'static str),
-    UnexpectedToken(char),
+    /* The lexer did not reach its end state when the input ended */
+    UnfinishedInput,
+    EmptyInput,
+    UnexpectedToken(char, Span),
+    /* From the opening quote to the end of input */
+    UnterminatedString(Span),
+    Limit(LimitError),
 }
 
 impl std::fmt::Display for LexerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LexerError::Error(msg) => write!(f, "{}", msg),
-            LexerError::UnexpectedToken(ch) => write!(f, "Unexpected token {}", ch),
+            LexerError::UnfinishedInput => {
+                write!(f, "Lexer is not at END state when the input ended")
+            }
+            LexerError::EmptyInput => write!(f, "Empty input string"),
+            LexerError::UnexpectedToken(ch, _) => write!(f, "Unexpected token {}", ch),
+            LexerError::UnterminatedString(_) => write!(f, "Unterminated string"),
+            LexerError::Limit(error) => write!(f, "{}", error),
         }
     }
 }
 
+/* Limit errors are displayed as they are, so they are not a source as well */
+impl std::error::Error for LexerError {}
+
 impl<E> From<LexerError> for ScriptError<E>
 where
     E: RuntimeError,
 {
     fn from(error: LexerError) -> Self {
-        ScriptError::Lexer(error)
+        match error {
+            LexerError::Limit(error) => ScriptError::Limit(error),
+            error => ScriptError::Lexer(error),
+        }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseError {
-    Error(&'static str),
     IncorrectParseResult,
     UnexpectedSymbol(String),
     GrammarDoesNotExist(usize),
     StateDoesNotExist(usize),
+    /* A rule whose reducer is never_reducer was reduced */
+    UnreachableReducer,
+    EmptyParseStack,
+    /* Rule number that has more rvals than the parse stack has states */
+    StackUnderflow(usize),
+    /* State and rule number without a goto for the lval of the rule */
+    MissingGoto(usize, usize),
+    /* State whose action on a terminal is a goto */
+    UnexpectedGoto(usize),
+    /* Number of AST stack items when the input was accepted, instead of one */
+    UnbalancedStack(usize),
+    EditOutsideSource(Span),
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ParseError::Error(msg) => write!(f, "{}", msg),
             ParseError::UnexpectedSymbol(symbol) => write!(f, "Unexpected symbol {}", symbol),
-            ParseError::GrammarDoesNotExist(rule) => write!(f, "Grammar rule {} does not exist", rule),
+            ParseError::GrammarDoesNotExist(rule) => {
+                write!(f, "Grammar rule {} does not exist", rule)
+            }
             ParseError::StateDoesNotExist(state) => write!(f, "state {} does not exist", state),
-            ParseError::IncorrectParseResult => write!(f, "AST evaluation final result is not a value"),
+            ParseError::IncorrectParseResult => {
+                write!(f, "AST evaluation final result is not a value")
+            }
+            ParseError::UnreachableReducer => {
+                write!(f, "Reach a reducer that should never be reached")
+            }
+            ParseError::EmptyParseStack => write!(f, "Parse stack is empty"),
+            ParseError::StackUnderflow(rule) => {
+                write!(
+                    f,
+                    "Parse stack does not have enough items to reduce rule {}",
+                    rule
+                )
+            }
+            ParseError::MissingGoto(state, rule) => {
+                write!(
+                    f,
+                    "State {} has no goto for the lval of rule {}",
+                    state, rule
+                )
+            }
+            ParseError::UnexpectedGoto(state) => {
+                write!(f, "Unexpected goto action on a terminal in state {}", state)
+            }
+            ParseError::UnbalancedStack(items) => {
+                write!(
+                    f,
+                    "Accepted with {} items on the AST stack instead of one",
+                    items
+                )
+            }
+            ParseError::EditOutsideSource(span) => {
+                write!(
+                    f,
+                    "Text edit {}..{} is outside of the source",
+                    span.start, span.end
+                )
+            }
         }
     }
 }
 
-#[derive(Debug)]
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SyntaxError {
-    SyntaxError
+    /* Input ended before the grammar accepted it */
+    SyntaxError,
+    /* The token found, where it is and the terminals the parser would have accepted there */
+    UnexpectedToken(String, Span, Vec<String>),
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxError::SyntaxError => write!(f, "Unexpected end of input"),
+            SyntaxError::UnexpectedToken(token, _, _) => write!(f, "Unexpected token {}", token),
+        }
+    }
 }
 
+impl std::error::Error for SyntaxError {}
+
 impl<E> From<GrammarError> for ScriptError<E>
 where
     E: RuntimeError,
@@ -129,4 +314,86 @@ where
     }
 }
 
-pub trait RuntimeError: std::fmt::Display {}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReducerError {
+    MissingArgument(usize, usize),
+    UnexpectedNode(&'static str, &'static str),
+    UnknownLabel(&'static str),
+}
+
+impl std::fmt::Display for ReducerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReducerError::MissingArgument(idx, arity) => {
+                write!(
+                    f,
+                    "Argument {} does not exist, the rule has {} arguments",
+                    idx, arity
+                )
+            }
+            ReducerError::UnexpectedNode(expected, found) => {
+                write!(f, "Expected {} but {} was given", expected, found)
+            }
+            ReducerError::UnknownLabel(label) => {
+                write!(f, "Label {} is not defined in the grammar rule", label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReducerError {}
+
+impl<E> From<ReducerError> for ScriptError<E>
+where
+    E: RuntimeError,
+{
+    fn from(error: ReducerError) -> Self {
+        ScriptError::Reducer(error)
+    }
+}
+
+/* A bound set in ExecutionLimits was exceeded, each carries the configured maximum */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    SourceTooLong(usize),
+    TooManyTokens(usize),
+    ParseStackTooDeep(usize),
+    EvaluationTooDeep(usize),
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitError::SourceTooLong(max) => {
+                write!(f, "Source is longer than the maximum of {} bytes", max)
+            }
+            LimitError::TooManyTokens(max) => {
+                write!(f, "Source has more than the maximum of {} tokens", max)
+            }
+            LimitError::ParseStackTooDeep(max) => {
+                write!(f, "Parse stack is deeper than the maximum of {}", max)
+            }
+            LimitError::EvaluationTooDeep(max) => {
+                write!(f, "Evaluation is nested deeper than the maximum of {}", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl<E> From<LimitError> for ScriptError<E>
+where
+    E: RuntimeError,
+{
+    fn from(error: LimitError) -> Self {
+        ScriptError::Limit(error)
+    }
+}
+
+pub trait RuntimeError: std::fmt::Display {
+    /* Errors that implement std::error::Error return themselves so that ScriptError::source reaches them */
+    fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
@@ -0,0 +1,255 @@
+/*
+Random and exhaustive sentences of a grammar for property tests
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use super::grammar::{GrammarSet, Symbol};
+use super::token::{ParserToken, Token};
+
+#[derive(Clone)]
+pub struct GenerateOptions<T: ParserToken<T>> {
+    seed: u64,
+    max_depth: usize,
+    count: usize,
+    exhaustive: bool,
+    /* Source text to pick from for terminals whose name is not their text, like id or int */
+    values: HashMap<T, Vec<&'static str>>,
+}
+
+impl<T: ParserToken<T>> GenerateOptions<T> {
+    pub fn new() -> GenerateOptions<T> {
+        GenerateOptions {
+            seed: 0,
+            max_depth: 8,
+            count: 100,
+            exhaustive: false,
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> GenerateOptions<T> {
+        self.seed = seed;
+        self
+    }
+
+    /* Rules applied along any path from the start rule to a terminal */
+    pub fn max_depth(mut self, depth: usize) -> GenerateOptions<T> {
+        self.max_depth = depth;
+        self
+    }
+
+    /* Number of random sentences, or the most exhaustive generation returns */
+    pub fn count(mut self, count: usize) -> GenerateOptions<T> {
+        self.count = count;
+        self
+    }
+
+    /* Every sentence within max_depth in rule order, up to count */
+    pub fn exhaustive(mut self) -> GenerateOptions<T> {
+        self.exhaustive = true;
+        self
+    }
+
+    pub fn values(mut self, terminal: T, values: &[&'static str]) -> GenerateOptions<T> {
+        self.values.insert(terminal, values.to_vec());
+        self
+    }
+}
+
+impl<T: ParserToken<T>> Default for GenerateOptions<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/* Generated tokens ending with eof and the source text they are lexed from */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentence<T: ParserToken<T>> {
+    pub tokens: Vec<Token<T>>,
+    pub source: String,
+}
+
+/* SplitMix64, seeds that are close still give unrelated sequences */
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/* Terminals derived so far, in order */
+type Derivation = Vec<usize>;
+
+struct Generator<'a, T: ParserToken<T>> {
+    grammar_set: &'a GrammarSet<T>,
+    /* Rule indexes by the symbol id of their left hand side */
+    rules: Vec<Vec<usize>>,
+    /* Depth of the shallowest derivation of each symbol, None when it derives no sentence */
+    heights: Vec<Option<usize>>,
+    limit: usize,
+    memo: HashMap<(usize, usize), Vec<Derivation>>,
+}
+
+impl<T: ParserToken<T>> GrammarSet<T> {
+    /* Sentences of the start rule within the depth bound, none when the grammar needs more depth */
+    pub fn generate(&self, options: &GenerateOptions<T>) -> Vec<Sentence<T>> {
+        let start = match self.grammars.first() {
+            Some(grammar) => grammar.lval_id,
+            None => return vec![],
+        };
+        let mut generator = Generator::new(self, options.count);
+        let mut rng = Rng(options.seed);
+        let fits = generator.heights[start].is_some_and(|height| height <= options.max_depth);
+        let derivations = match (fits, options.exhaustive) {
+            (false, _) => vec![],
+            (true, true) => generator.expand_all(start, options.max_depth),
+            (true, false) => (0..options.count)
+                .map(|_| {
+                    let mut derivation = vec![];
+                    generator.expand(start, options.max_depth, &mut rng, &mut derivation);
+                    derivation
+                })
+                .collect(),
+        };
+        let names: HashMap<_, _> = self
+            .terminal_symbols
+            .iter()
+            .filter_map(|(&name, symbol)| Some((self.symbol_id(symbol)?, name)))
+            .collect();
+        derivations
+            .into_iter()
+            .map(|derivation| {
+                let mut tokens = vec![];
+                let mut words = vec![];
+                for id in derivation {
+                    let Symbol::Terminal(terminal) = *self.symbols[id] else {
+                        continue;
+                    };
+                    let value = match options.values.get(&terminal) {
+                        Some(values) if !values.is_empty() => values[rng.below(values.len())],
+                        _ if terminal == self.eof => "",
+                        _ => names.get(&id).copied().unwrap_or_default(),
+                    };
+                    if terminal != self.eof {
+                        words.push(value);
+                    }
+                    tokens.push(terminal.entity(value.to_string()));
+                }
+                Sentence {
+                    tokens,
+                    source: words.join(" "),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<'a, T: ParserToken<T>> Generator<'a, T> {
+    fn new(grammar_set: &'a GrammarSet<T>, limit: usize) -> Generator<'a, T> {
+        let mut rules = vec![vec![]; grammar_set.symbols.len()];
+        for (rule_idx, grammar) in grammar_set.grammars.iter().enumerate() {
+            rules[grammar.lval_id].push(rule_idx);
+        }
+        let mut heights = vec![None; grammar_set.symbols.len()];
+        heights[..grammar_set.terminal_count].fill(Some(0));
+        let mut generator = Generator {
+            grammar_set,
+            rules,
+            heights,
+            limit,
+            memo: HashMap::new(),
+        };
+        /* a rule is one deeper than its deepest symbol, until nothing gets shallower */
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (rule_idx, grammar) in grammar_set.grammars.iter().enumerate() {
+                let height = generator.rule_height(rule_idx);
+                let current = generator.heights[grammar.lval_id];
+                if height.is_some() && (current.is_none() || height < current) {
+                    generator.heights[grammar.lval_id] = height;
+                    changed = true;
+                }
+            }
+        }
+        generator
+    }
+
+    fn rule_height(&self, rule_idx: usize) -> Option<usize> {
+        let grammar = &self.grammar_set.grammars[rule_idx];
+        let heights = grammar.rval_ids.iter().map(|&id| self.heights[id]);
+        Some(
+            heights
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .max()
+                .unwrap_or(0)
+                + 1,
+        )
+    }
+
+    /* Rules of the symbol that finish within depth */
+    fn fitting(&self, symbol: usize, depth: usize) -> Vec<usize> {
+        let rules = self.rules[symbol].iter().copied();
+        rules
+            .filter(|&rule_idx| self.rule_height(rule_idx).is_some_and(|h| h <= depth))
+            .collect()
+    }
+
+    /* Picks a fitting rule at random, there always is one as the caller checked the height */
+    fn expand(&self, symbol: usize, depth: usize, rng: &mut Rng, derivation: &mut Derivation) {
+        if symbol < self.grammar_set.terminal_count {
+            derivation.push(symbol);
+            return;
+        }
+        let rules = self.fitting(symbol, depth);
+        let grammar = &self.grammar_set.grammars[rules[rng.below(rules.len())]];
+        for &id in &grammar.rval_ids {
+            self.expand(id, depth - 1, rng, derivation);
+        }
+    }
+
+    /* Every derivation of the symbol within depth, cut at the limit */
+    fn expand_all(&mut self, symbol: usize, depth: usize) -> Vec<Derivation> {
+        if symbol < self.grammar_set.terminal_count {
+            return vec![vec![symbol]];
+        }
+        if let Some(derivations) = self.memo.get(&(symbol, depth)) {
+            return derivations.clone();
+        }
+        let mut derivations = vec![];
+        let mut seen = HashSet::new();
+        for rule_idx in self.fitting(symbol, depth) {
+            let mut partial = vec![vec![]];
+            for id in self.grammar_set.grammars[rule_idx].rval_ids.clone() {
+                let tails = self.expand_all(id, depth - 1);
+                partial = partial
+                    .iter()
+                    .flat_map(|head: &Derivation| {
+                        tails.iter().map(move |tail| [&head[..], tail].concat())
+                    })
+                    .take(self.limit)
+                    .collect();
+            }
+            /* an ambiguous grammar derives a sentence more than once */
+            let fresh = partial.into_iter().filter(|d| seen.insert(d.clone()));
+            derivations.extend(fresh);
+            if derivations.len() >= self.limit {
+                derivations.truncate(self.limit);
+                break;
+            }
+        }
+        self.memo.insert((symbol, depth), derivations.clone());
+        derivations
+    }
+}
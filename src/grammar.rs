@@ -1,6 +1,14 @@
-use std::{collections::HashMap, fmt::Display, hash::Hash, rc::Rc};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Display,
+    hash::Hash,
+    sync::Arc,
+};
+
+use ry_script_lr::{symbol_names, RuleError, RuleText};
 
 use super::error::{GrammarError, RuntimeError};
+use super::table::fingerprint;
 use super::token::ParserToken;
 use super::{ast::RuntimeValue, runner::GrammarRule};
 
@@ -20,11 +28,14 @@ impl<T: ParserToken<T>> Display for Symbol<T> {
     }
 }
 
-#[derive(Debug, Hash, Eq)]
+#[derive(Debug, Eq)]
 pub struct Grammar<T: ParserToken<T>> {
     pub rule_number: usize,
-    pub lval: Rc<Symbol<T>>,
-    pub rvals: Vec<Rc<Symbol<T>>>,
+    pub lval: Arc<Symbol<T>>,
+    pub rvals: Vec<Arc<Symbol<T>>>,
+    pub lval_id: usize,
+    pub rval_ids: Vec<usize>,
+    pub labels: Vec<Option<&'static str>>,
 }
 
 impl<T: ParserToken<T>> PartialEq for Grammar<T> {
@@ -33,114 +44,200 @@ impl<T: ParserToken<T>> PartialEq for Grammar<T> {
     }
 }
 
+impl<T: ParserToken<T>> Hash for Grammar<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.rule_number.hash(state);
+    }
+}
+
 impl<T: ParserToken<T>> Display for Grammar<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{} -> ", self.lval)?;
-        for rval in &self.rvals {
+        for (rval, label) in self.rvals.iter().zip(&self.labels) {
+            if let Some(label) = label {
+                write!(f, "{}:", label)?;
+            }
             write!(f, "{} ", rval)?
         }
         Ok(())
     }
 }
 
-type SymbolMap<T> = HashMap<&'static str, Rc<Symbol<T>>>;
+type SymbolMap<T> = HashMap<&'static str, Arc<Symbol<T>>>;
 
 #[derive(Clone, Copy)]
 pub struct TerminalSymbolDef<T: ParserToken<T>>(pub &'static str, pub T);
 
+/* Symbols are numbered as grammar! numbers them, terminals first in order of first use in the rules, then
+non-terminals in rule order. Terminals the rules never use have no id */
 pub struct GrammarSet<T: ParserToken<T>> {
-    pub grammars: Vec<Rc<Grammar<T>>>,
+    pub grammars: Vec<Arc<Grammar<T>>>,
     pub eof: T,
     pub terminal_symbols: SymbolMap<T>,
     pub non_terminal_symbols: SymbolMap<T>,
+    pub symbols: Vec<Arc<Symbol<T>>>,
+    pub terminal_count: usize,
+    symbol_ids: HashMap<Arc<Symbol<T>>, usize>,
 }
 
 impl<T: ParserToken<T>> GrammarSet<T> {
     /* Pre-condition: The first grammar is expected to be the starter grammar */
     pub fn new<ENV, R: RuntimeValue<T>, E: RuntimeError>(
-        grammars: &Vec<GrammarRule<ENV, T, R, E>>,
-        terminals: &Vec<TerminalSymbolDef<T>>,
+        grammars: &[GrammarRule<ENV, T, R, E>],
+        terminals: &[TerminalSymbolDef<T>],
         eof: T,
     ) -> Result<GrammarSet<T>, GrammarError> {
+        let texts = grammars.iter().enumerate().map(|(i, text)| {
+            RuleText::parse(text.text).map_err(|error| match error {
+                RuleError::InvalidText => {
+                    GrammarError::InvalidGrammarText(i + 1, text.text.to_string())
+                }
+                RuleError::DuplicateLabel(token) => GrammarError::DuplicateLabel(i + 1, token),
+            })
+        });
+        let texts = texts.collect::<Result<Vec<_>, _>>()?;
         // terminal symbols
         let mut terminal_symbols = HashMap::new();
         terminals.iter().for_each(|def| {
-            terminal_symbols.insert(def.0, Rc::new(Symbol::Terminal(def.1)));
-            ()
+            terminal_symbols.insert(def.0, Arc::new(Symbol::Terminal(def.1)));
         });
         // non-terminal symbols
         let mut non_terminal_symbols = HashMap::new();
         for text in grammars {
-            let mut tokens = text.0.split(" ");
-            let lval = match tokens.next() {
-                Some(token) => token,
-                None => return Err(GrammarError::InvalidGrammarText(text.0)),
-            };
-            non_terminal_symbols.insert(lval, Rc::new(Symbol::NonTerminal(lval)));
+            let lval = text.text.split(' ').next().unwrap_or_default();
+            non_terminal_symbols.insert(lval, Arc::new(Symbol::NonTerminal(lval)));
+        }
+        // symbol ids, names that are not symbols are reported when their rule is parsed
+        let mut symbols = vec![];
+        let mut symbol_ids = HashMap::new();
+        let (terminal_names, lval_names) = symbol_names(&texts);
+        let terminal_names = terminal_names
+            .into_iter()
+            .filter_map(|name| terminal_symbols.get(name));
+        let lval_names = lval_names
+            .into_iter()
+            .filter_map(|name| non_terminal_symbols.get(name));
+        let mut terminal_count = 0;
+        for symbol in terminal_names.chain(lval_names) {
+            if let Entry::Vacant(v) = symbol_ids.entry(Arc::clone(symbol)) {
+                v.insert(symbols.len());
+                symbols.push(Arc::clone(symbol));
+            }
+            if let Symbol::Terminal(_) = **symbol {
+                terminal_count = symbols.len();
+            }
         }
         let mut grammar = GrammarSet {
             grammars: vec![],
             terminal_symbols,
             non_terminal_symbols,
+            symbols,
+            terminal_count,
+            symbol_ids,
             eof,
         };
-        for text in grammars {
-            grammar.parse_grammar(text.0)?;
+        for (text, rule_text) in grammars.iter().zip(&texts) {
+            grammar.parse_grammar(text.text, rule_text)?;
         }
         Ok(grammar)
     }
 
-    fn get_symbol(&self, symbol: &'static str) -> Option<Rc<Symbol<T>>> {
+    pub fn get_symbol(&self, symbol: &str) -> Option<Arc<Symbol<T>>> {
         if let Some(terminal_symbol) = self.terminal_symbols.get(symbol) {
-            Some(Rc::clone(terminal_symbol))
+            Some(Arc::clone(terminal_symbol))
         } else if let Some(non_terminal_symbol) = self.non_terminal_symbols.get(symbol) {
-            Some(Rc::clone(non_terminal_symbol))
+            Some(Arc::clone(non_terminal_symbol))
         } else {
-            #[cfg(feature = "debug_grammar")]
-            println!("cannot find symbol {}", symbol);
             None
         }
     }
 
-    fn parse_grammar(&mut self, text: &'static str) -> Result<(), GrammarError> {
-        let mut tokens = text.split(" ");
-        let lval = match tokens.next() {
-            Some(token) => match self.get_symbol(token) {
-                Some(symbol) => match *symbol {
-                    Symbol::NonTerminal(_) => symbol,
-                    _ => return Err(GrammarError::InvalidSymbol(token)),
-                },
-                None => return Err(GrammarError::InvalidSymbol(token)),
+    pub fn symbol_id(&self, symbol: &Symbol<T>) -> Option<usize> {
+        self.symbol_ids.get(symbol).copied()
+    }
+
+    pub fn terminal_id(&self, terminal: T) -> Option<usize> {
+        self.symbol_id(&Symbol::Terminal(terminal))
+    }
+
+    fn parse_grammar(
+        &mut self,
+        text: &'static str,
+        rule_text: &RuleText,
+    ) -> Result<(), GrammarError> {
+        let rule = self.grammars.len() + 1;
+        let lval = match self.get_symbol(&rule_text.lval) {
+            Some(symbol) => match *symbol {
+                Symbol::NonTerminal(_) => symbol,
+                _ => return Err(GrammarError::InvalidSymbol(rule, rule_text.lval.clone())),
             },
-            None => return Err(GrammarError::InvalidGrammarText(text)),
-        };
-        match tokens.next() {
-            Some(token) if token == "->" => (),
-            _ => return Err(GrammarError::InvalidGrammarText(text)),
+            None => return Err(GrammarError::InvalidSymbol(rule, rule_text.lval.clone())),
         };
-        let rvals = match tokens
-            .map(|token| self.get_symbol(token))
-            .collect::<Option<Vec<_>>>()
-        {
-            Some(rvals) => rvals,
-            None => return Err(GrammarError::InvalidGrammarText(text)),
+        let Some(&lval_id) = self.symbol_ids.get(&lval) else {
+            return Err(GrammarError::InvalidSymbol(rule, rule_text.lval.clone()));
         };
-        let grammar = Rc::new(Grammar {
-            rule_number: self.grammars.len() + 1,
+        let mut rvals = vec![];
+        let mut rval_ids = vec![];
+        let mut labels = vec![];
+        /* RuleText owns its labels, the static ones are taken from the rval tokens of the text */
+        for (rval, token) in rule_text.rvals.iter().zip(text.split(' ').skip(2)) {
+            /* a terminal that is also a rule lval is numbered as the non-terminal and has no id */
+            let (symbol, id) = match self.get_symbol(&rval.symbol) {
+                Some(symbol) => match self.symbol_ids.get(&symbol) {
+                    Some(&id) => (symbol, id),
+                    None => return Err(GrammarError::InvalidSymbol(rule, rval.symbol.clone())),
+                },
+                None => return Err(GrammarError::InvalidSymbol(rule, rval.symbol.clone())),
+            };
+            let label = match rval.label {
+                Some(_) => token.split_once(':').map(|(label, _)| label),
+                None => None,
+            };
+            rvals.push(symbol);
+            rval_ids.push(id);
+            labels.push(label);
+        }
+        let grammar = Arc::new(Grammar {
+            rule_number: rule,
             lval,
             rvals,
+            lval_id,
+            rval_ids,
+            labels,
         });
-        #[cfg(feature = "debug_grammar")]
-        println!("Parsed grammar: {}", grammar);
         self.grammars.push(grammar);
         Ok(())
     }
 
-    pub fn find_grammars(&self, lval: Rc<Symbol<T>>) -> Vec<Rc<Grammar<T>>> {
+    pub fn symbol_name(&self, symbol: &Symbol<T>) -> Option<&'static str> {
+        match symbol {
+            Symbol::NonTerminal(name) => Some(name),
+            Symbol::Terminal(_) => self
+                .terminal_symbols
+                .iter()
+                .find(|(_, s)| ***s == *symbol)
+                .map(|(&name, _)| name),
+        }
+    }
+
+    /* Identifies the rules and terminal definitions, a saved parse table is only valid for the same fingerprint */
+    pub fn fingerprint(&self) -> u64 {
+        let mut terminals: Vec<String> = self
+            .terminal_symbols
+            .iter()
+            .map(|(name, symbol)| format!("{}={}", name, symbol))
+            .collect();
+        terminals.sort();
+        let rules = self.grammars.iter().map(|g| format!("{}", g));
+        let parts: Vec<String> = rules.chain(terminals).collect();
+        fingerprint(parts.iter().map(String::as_str))
+    }
+
+    pub fn find_grammars(&self, lval: Arc<Symbol<T>>) -> Vec<Arc<Grammar<T>>> {
         self.grammars
             .iter()
             .filter(|&g| lval == g.lval)
-            .map(|g| Rc::clone(g))
+            .map(Arc::clone)
             .collect()
     }
 }
@@ -1,4 +1,10 @@
-use super::{token::{Token, SpecialTokenMap, Tokens, ParserToken, LexerTokenMap}, error::LexerError};
+use super::{
+    error::{LexerError, LimitError},
+    limits,
+    token::{
+        LexerTokenMap, ParserToken, Span, SpecialTokenMap, TextEdit, Token, TokenChange, Tokens,
+    },
+};
 
 #[derive(Debug, PartialEq, Eq)]
 enum LexerState {
@@ -20,12 +26,21 @@ struct LexerResult<T: ParserToken<T>> {
     move_cursor: bool,
 }
 
-pub struct Lexer<T: ParserToken<T>> {
+/* State of a single parse call, kept out of Lexer so that one lexer can be shared */
+struct LexerContext<T: ParserToken<T>> {
     state: LexerState,
     buffer: String,
     tokens: Vec<Token<T>>,
+    spans: Vec<Span>,
+    /* Byte offset where the token being lexed starts */
+    start: usize,
+    /* Errors recorded in recovering mode, None when the first error ends the parse */
+    errors: Option<Vec<LexerError>>,
+}
+
+pub struct Lexer<T: ParserToken<T>> {
     special_token_map: SpecialTokenMap<T>,
-    token_map: LexerTokenMap<T>
+    token_map: LexerTokenMap<T>,
 }
 
 impl<T: ParserToken<T>> Lexer<T> {
@@ -36,9 +51,9 @@ impl<T: ParserToken<T>> Lexer<T> {
         move_cursor: false,
     };
 
-    fn handle_normal_state(&self, ch: char) -> Result<LexerResult<T>, LexerError> {
+    fn handle_normal_state(&self, ch: char, pos: usize) -> Result<LexerResult<T>, LexerError> {
         let result = match ch {
-            '_' | _ if ch.is_alphabetic() => LexerResult {
+            _ if ch == '_' || ch.is_alphabetic() => LexerResult {
                 state: LexerState::Identifier,
                 create: None,
                 buffer: true,
@@ -80,22 +95,26 @@ impl<T: ParserToken<T>> Lexer<T> {
                 buffer: false,
                 move_cursor: false,
             },
-            _ => return Err(LexerError::UnexpectedToken(ch)),
+            _ => return Err(LexerError::UnexpectedToken(ch, char_span(pos, ch))),
         };
         Ok(result)
     }
 
-    fn handle_identifier_state(&self, ch: char) -> Result<LexerResult<T>, LexerError> {
+    fn handle_identifier_state(
+        &self,
+        ch: char,
+        buffer: &str,
+    ) -> Result<LexerResult<T>, LexerError> {
         let result = match ch {
-            '_' | _ if ch.is_ascii_alphanumeric() => LexerResult {
+            _ if ch == '_' || ch.is_ascii_alphanumeric() => LexerResult {
                 state: LexerState::Identifier,
                 create: None,
                 buffer: true,
                 move_cursor: true,
             },
-            _ if self.special_token_map.is_keyword(&self.buffer) => LexerResult {
+            _ if self.special_token_map.is_keyword(buffer) => LexerResult {
                 state: LexerState::Normal,
-                create: self.special_token_map.get_keyword_type(&self.buffer),
+                create: self.special_token_map.get_keyword_type(buffer),
                 buffer: false,
                 move_cursor: false,
             },
@@ -109,8 +128,13 @@ impl<T: ParserToken<T>> Lexer<T> {
         Ok(result)
     }
 
-    fn handle_sign_state(&self, ch: char) -> Result<LexerResult<T>, LexerError> {
-        let mut tmp = self.buffer.clone();
+    fn handle_sign_state(
+        &self,
+        ch: char,
+        pos: usize,
+        buffer: &str,
+    ) -> Result<LexerResult<T>, LexerError> {
+        let mut tmp = buffer.to_string();
         tmp.push(ch);
         let result = if self.special_token_map.is_valid_sign(&tmp) {
             LexerResult {
@@ -119,7 +143,7 @@ impl<T: ParserToken<T>> Lexer<T> {
                 buffer: true,
                 move_cursor: true,
             }
-        } else if let Some(token) = self.special_token_map.get_sign_type(&self.buffer) {
+        } else if let Some(token) = self.special_token_map.get_sign_type(buffer) {
             LexerResult {
                 state: LexerState::Normal,
                 create: Some(token),
@@ -127,7 +151,10 @@ impl<T: ParserToken<T>> Lexer<T> {
                 move_cursor: false,
             }
         } else {
-            return Err(LexerError::UnexpectedToken(ch));
+            /* the buffer is a prefix of an operator but not an operator itself */
+            let start = pos - buffer.len();
+            let first = buffer.chars().next().unwrap_or(ch);
+            return Err(LexerError::UnexpectedToken(first, Span { start, end: pos }));
         };
         Ok(result)
     }
@@ -174,9 +201,9 @@ impl<T: ParserToken<T>> Lexer<T> {
         Ok(result)
     }
 
-    fn handle_string_state(&self, ch: char) -> Result<LexerResult<T>, LexerError> {
-        let result= match ch {
-            '\0' => return Err(LexerError::Error("Unexpected EOF")),
+    fn handle_string_state(&self, ch: char, span: Span) -> Result<LexerResult<T>, LexerError> {
+        let result = match ch {
+            '\0' => return Err(LexerError::UnterminatedString(span)),
             '"' => LexerResult {
                 // TODO implement escape character \"
                 state: LexerState::Normal,
@@ -214,67 +241,265 @@ impl<T: ParserToken<T>> Lexer<T> {
 
     pub fn new(token_map: LexerTokenMap<T>, special_token_map: SpecialTokenMap<T>) -> Lexer<T> {
         Lexer {
+            token_map,
+            special_token_map,
+        }
+    }
+
+    pub fn token_map(&self) -> &LexerTokenMap<T> {
+        &self.token_map
+    }
+
+    pub fn special_token_map(&self) -> &SpecialTokenMap<T> {
+        &self.special_token_map
+    }
+
+    pub fn parse(&self, input: &str) -> Result<Tokens<T>, LexerError> {
+        Ok(self.lex(input, None, &mut |_| {})?.0)
+    }
+
+    /* Calls on_token with every token as soon as it is created, tokens before an error included */
+    pub fn parse_with(
+        &self,
+        input: &str,
+        mut on_token: impl FnMut(&Token<T>),
+    ) -> Result<Tokens<T>, LexerError> {
+        Ok(self.lex(input, None, &mut on_token)?.0)
+    }
+
+    /* Keeps going after unexpected characters and unterminated strings, each becomes an error token when LexerTokenMap::error is set */
+    pub fn parse_recovering(
+        &self,
+        input: &str,
+    ) -> Result<(Tokens<T>, Vec<LexerError>), LexerError> {
+        let (tokens, errors) = self.lex(input, Some(vec![]), &mut |_| {})?;
+        Ok((tokens, errors.unwrap_or_default()))
+    }
+
+    fn lex(
+        &self,
+        input: &str,
+        errors: Option<Vec<LexerError>>,
+        on_token: &mut dyn FnMut(&Token<T>),
+    ) -> Result<(Tokens<T>, Option<Vec<LexerError>>), LexerError> {
+        limits::check_source_len(input.len()).map_err(LexerError::Limit)?;
+        let max_tokens = limits::max_tokens();
+        if input.is_empty() {
+            return Err(LexerError::EmptyInput);
+        }
+        let mut context = LexerContext {
             state: LexerState::Normal,
             buffer: String::new(),
             tokens: Vec::new(),
-            token_map,
-            special_token_map,
+            spans: Vec::new(),
+            start: 0,
+            errors,
+        };
+        self.scan(input, &mut context, 0, max_tokens, on_token, |_| false)?;
+        match context.state {
+            LexerState::End => Ok((Tokens(context.tokens, context.spans), context.errors)),
+            _ => Err(LexerError::UnfinishedInput),
         }
     }
 
-    fn reset(&mut self) {
-        self.state = LexerState::Normal;
-        self.buffer.clear();
-        self.tokens.clear();
+    /* Lexes the new source of an edit again from the last token that ends before the edit until the tokens line up with the old ones */
+    pub fn relex(
+        &self,
+        tokens: &Tokens<T>,
+        edit: &TextEdit,
+        input: &str,
+    ) -> Result<(Tokens<T>, TokenChange), LexerError> {
+        limits::check_source_len(input.len()).map_err(LexerError::Limit)?;
+        if input.is_empty() {
+            return Err(LexerError::EmptyInput);
+        }
+        let Tokens(old_tokens, old_spans) = tokens;
+        /* the lexer is in the normal state right after every token */
+        let start = old_spans.partition_point(|span| span.end < edit.span.start);
+        let from = start.checked_sub(1).map_or(0, |last| old_spans[last].end);
+        let mut context = LexerContext {
+            state: LexerState::Normal,
+            buffer: String::new(),
+            tokens: old_tokens[..start].to_vec(),
+            spans: old_spans[..start].to_vec(),
+            start: from,
+            errors: None,
+        };
+        /* a token that ends where an old one ended after the edit leaves the lexer where it was */
+        let delta = edit.delta();
+        let mut old_end = old_tokens.len();
+        self.scan(input, &mut context, from, limits::max_tokens(), &mut |_| {}, |context| {
+            let end = match context.spans.last() {
+                Some(span) if context.spans.len() > start => span.end,
+                _ => return false,
+            };
+            if end < edit.span.start + edit.text.len() {
+                return false;
+            }
+            /* eof is empty and only ends input, the lexer is not in the normal state after it */
+            let old = (end as isize - delta) as usize;
+            let index = old_spans.partition_point(|span| span.end < old);
+            match old_spans.get(index) {
+                Some(span) if span.end == old && span.start < span.end => {
+                    old_end = index + 1;
+                    true
+                }
+                _ => false,
+            }
+        })?;
+        let new_end = context.tokens.len();
+        if context.state != LexerState::End {
+            context.tokens.extend_from_slice(&old_tokens[old_end..]);
+            context
+                .spans
+                .extend(old_spans[old_end..].iter().map(|span| Span {
+                    start: (span.start as isize + delta) as usize,
+                    end: (span.end as isize + delta) as usize,
+                }));
+        }
+        let change = TokenChange {
+            start,
+            old_end,
+            new_end,
+        };
+        Ok((Tokens(context.tokens, context.spans), change))
     }
 
-    pub fn parse(&mut self, input: &str) -> Result<Tokens<T>, LexerError> {
-        self.reset();
-        let mut iter = input.chars();
+    /* Runs the state machine from a byte offset until the end of input, or until resync accepts the token just created */
+    fn scan(
+        &self,
+        input: &str,
+        context: &mut LexerContext<T>,
+        from: usize,
+        max_tokens: Option<usize>,
+        on_token: &mut dyn FnMut(&Token<T>),
+        mut resync: impl FnMut(&LexerContext<T>) -> bool,
+    ) -> Result<(), LexerError> {
+        let mut iter = input[from..]
+            .char_indices()
+            .map(|(pos, ch)| (pos + from, ch));
         let mut move_cursor = false;
-        let mut next_char: char = match iter.next() {
-            Some(ch) => ch,
-            None => return Err(LexerError::Error("Empty input string")),
-        };
-        while self.state != LexerState::End && self.state != LexerState::Error {
+        let (mut pos, mut next_char) = iter.next().unwrap_or((input.len(), '\0'));
+        while context.state != LexerState::End && context.state != LexerState::Error {
             if move_cursor {
-                next_char = match iter.next() {
-                    Some(ch) => ch,
-                    None => '\0',
-                };
+                (pos, next_char) = iter.next().unwrap_or((input.len(), '\0'));
+            }
+            let count = context.tokens.len();
+            move_cursor = self.parse_char(context, pos, next_char)?;
+            for token in &context.tokens[count..] {
+                on_token(token);
+            }
+            if let Some(max) = max_tokens.filter(|&max| context.tokens.len() > max) {
+                return Err(LexerError::Limit(LimitError::TooManyTokens(max)));
+            }
+            if context.tokens.len() > count
+                && context.state == LexerState::Normal
+                && resync(context)
+            {
+                break;
             }
-            move_cursor = self.parse_char(next_char)?;
-        }
-        match self.state {
-            LexerState::End => Ok(Tokens(std::mem::take(&mut self.tokens))),
-            _ => Err(LexerError::Error("Lexer is not at END State when parsing finished")),
         }
+        Ok(())
     }
 
-    fn parse_char(&mut self, ch: char) -> Result<bool, LexerError> {
-        #[cfg(feature = "debug_lexer")]
-        println!("{:?} -> {:?}", self.state, ch);
-        let res = match self.state {
-            LexerState::Normal => self.handle_normal_state(ch)?,
-            LexerState::Identifier => self.handle_identifier_state(ch)?,
-            LexerState::Sign => self.handle_sign_state(ch)?,
+    fn next_result(
+        &self,
+        context: &LexerContext<T>,
+        pos: usize,
+        ch: char,
+    ) -> Result<LexerResult<T>, LexerError> {
+        let result = match context.state {
+            LexerState::Normal => self.handle_normal_state(ch, pos)?,
+            LexerState::Identifier => self.handle_identifier_state(ch, &context.buffer)?,
+            LexerState::Sign => self.handle_sign_state(ch, pos, &context.buffer)?,
             LexerState::Integer => self.handle_integer_state(ch)?,
             LexerState::Float => self.handle_float_state(ch)?,
-            LexerState::String => self.handle_string_state(ch)?,
+            LexerState::String => self.handle_string_state(
+                ch,
+                Span {
+                    start: context.start,
+                    end: pos,
+                },
+            )?,
             LexerState::Comment => self.handle_comment_state(ch)?,
             LexerState::Error | LexerState::End => Lexer::ERROR_RESULT,
         };
-        self.state = res.state;
-        match res.create {
-            Some(token) => {
-                self.tokens.push(token.entity(std::mem::take(&mut self.buffer)));
-                self.buffer.clear();
+        Ok(result)
+    }
+
+    /* Records the error, replaces the bad input with an error token and continues in the normal state */
+    fn recover(
+        &self,
+        context: &mut LexerContext<T>,
+        error: LexerError,
+        ch: char,
+    ) -> Result<LexerResult<T>, LexerError> {
+        let (value, span, move_cursor) = match error {
+            /* a character that cannot start any token is skipped */
+            LexerError::UnexpectedToken(_, span) if context.state == LexerState::Normal => {
+                (ch.to_string(), span, true)
+            }
+            /* a partial operator or an unterminated string is dropped, the current character is lexed again */
+            LexerError::UnexpectedToken(_, span) | LexerError::UnterminatedString(span) => {
+                (std::mem::take(&mut context.buffer), span, false)
             }
-            None => (),
+            error => return Err(error),
+        };
+        if let Some(error_type) = self.token_map.error {
+            context.tokens.push(error_type.entity(value));
+            context.spans.push(span);
+        }
+        context.buffer.clear();
+        if let Some(errors) = &mut context.errors {
+            errors.push(error);
         }
-        if res.buffer == true {
-            self.buffer.push(ch);
+        Ok(LexerResult {
+            state: LexerState::Normal,
+            create: None,
+            buffer: false,
+            move_cursor,
+        })
+    }
+
+    fn parse_char(
+        &self,
+        context: &mut LexerContext<T>,
+        pos: usize,
+        ch: char,
+    ) -> Result<bool, LexerError> {
+        if context.state == LexerState::Normal {
+            context.start = pos;
+        }
+        let res = match self.next_result(context, pos, ch) {
+            Ok(res) => res,
+            Err(error) if context.errors.is_some() => self.recover(context, error, ch)?,
+            Err(error) => return Err(error),
+        };
+        context.state = res.state;
+        if let Some(token) = res.create {
+            context
+                .tokens
+                .push(token.entity(std::mem::take(&mut context.buffer)));
+            let end = if res.move_cursor {
+                pos + ch.len_utf8()
+            } else {
+                pos
+            };
+            context.spans.push(Span {
+                start: context.start,
+                end,
+            });
+        }
+        if res.buffer {
+            context.buffer.push(ch);
         }
         Ok(res.move_cursor)
     }
 }
+
+fn char_span(pos: usize, ch: char) -> Span {
+    Span {
+        start: pos,
+        end: pos + ch.len_utf8(),
+    }
+}
@@ -1,7 +1,20 @@
 pub mod ast;
+pub mod coverage;
+pub mod debugger;
+pub mod diagnostic;
+pub mod error;
+pub mod generate;
 pub mod grammar;
 pub mod lexer;
+pub mod limits;
 pub mod lrparser;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod observer;
 pub mod runner;
+pub mod syntax;
+pub mod table;
 pub mod token;
-pub mod error;
+
+#[cfg(feature = "derive")]
+pub use ry_script_derive::{grammar, TypedAst};
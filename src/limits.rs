@@ -0,0 +1,243 @@
+/*
+Bounds on the work of a single run, so that untrusted scripts cannot hang the host
+*/
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::error::{LimitError, ScriptError};
+
+/* Cancels every run that was given a clone of this token, checked before each evaluation */
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionLimits {
+    /* Number of expression evaluations a run may perform */
+    pub fuel: Option<u64>,
+    /* Wall-clock time a run may take, counted from its start */
+    pub timeout: Option<Duration>,
+    pub cancellation: Option<CancellationToken>,
+    /* Source length in bytes */
+    pub max_source_len: Option<usize>,
+    pub max_tokens: Option<usize>,
+    pub max_parse_depth: Option<usize>,
+    /* Nesting of expression evaluations */
+    pub max_eval_depth: Option<usize>,
+}
+
+impl ExecutionLimits {
+    pub fn fuel(mut self, fuel: u64) -> ExecutionLimits {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> ExecutionLimits {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> ExecutionLimits {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub fn max_source_len(mut self, len: usize) -> ExecutionLimits {
+        self.max_source_len = Some(len);
+        self
+    }
+
+    pub fn max_tokens(mut self, tokens: usize) -> ExecutionLimits {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    pub fn max_parse_depth(mut self, depth: usize) -> ExecutionLimits {
+        self.max_parse_depth = Some(depth);
+        self
+    }
+
+    pub fn max_eval_depth(mut self, depth: usize) -> ExecutionLimits {
+        self.max_eval_depth = Some(depth);
+        self
+    }
+
+    /* Installs the budget of a run on this thread until the guard is dropped, a nested run stays within the budget left to the run it is nested in */
+    pub(crate) fn enter(&self) -> BudgetGuard {
+        let mut budget = Budget {
+            fuel: self.fuel,
+            spent: 0,
+            deadline: self.timeout.map(|timeout| Instant::now() + timeout),
+            cancellation: self.cancellation.iter().cloned().collect(),
+            max_source_len: self.max_source_len,
+            max_tokens: self.max_tokens,
+            max_parse_depth: self.max_parse_depth,
+            max_eval_depth: self.max_eval_depth,
+            eval_depth: 0,
+        };
+        let outer = BUDGET.take();
+        if let Some(outer) = &outer {
+            budget.fuel = tighter(budget.fuel, outer.fuel);
+            budget.deadline = tighter(budget.deadline, outer.deadline);
+            budget
+                .cancellation
+                .extend(outer.cancellation.iter().cloned());
+            budget.max_eval_depth = tighter(
+                budget.max_eval_depth.map(|max| max + outer.eval_depth),
+                outer.max_eval_depth,
+            );
+            budget.eval_depth = outer.eval_depth;
+        }
+        BUDGET.set(Some(budget));
+        BudgetGuard(outer)
+    }
+}
+
+fn tighter<T: Ord>(limit: Option<T>, outer: Option<T>) -> Option<T> {
+    match (limit, outer) {
+        (Some(limit), Some(outer)) => Some(limit.min(outer)),
+        (limit, outer) => limit.or(outer),
+    }
+}
+
+struct Budget {
+    /* Fuel left, spent counts what this run used so that it can be taken from an enclosing run */
+    fuel: Option<u64>,
+    spent: u64,
+    deadline: Option<Instant>,
+    /* Tokens of this run and of the runs it is nested in */
+    cancellation: Vec<CancellationToken>,
+    max_source_len: Option<usize>,
+    max_tokens: Option<usize>,
+    max_parse_depth: Option<usize>,
+    max_eval_depth: Option<usize>,
+    eval_depth: usize,
+}
+
+impl Budget {
+    fn check<E>(&self) -> Result<(), ScriptError<E>> {
+        if self
+            .cancellation
+            .iter()
+            .any(CancellationToken::is_cancelled)
+        {
+            return Err(ScriptError::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(ScriptError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /* Evaluation happens on the thread that called run, nested runs stack their budgets */
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+}
+
+/* Restores the budget of an enclosing run when dropped, less the fuel the nested run spent */
+pub(crate) struct BudgetGuard(Option<Budget>);
+
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        let spent = BUDGET.with_borrow(|budget| budget.as_ref().map_or(0, |b| b.spent));
+        if let Some(outer) = &mut self.0 {
+            outer.fuel = outer.fuel.map(|fuel| fuel.saturating_sub(spent));
+            outer.spent += spent;
+        }
+        BUDGET.set(self.0.take());
+    }
+}
+
+/* Fails when the current run is cancelled or past its deadline */
+pub(crate) fn check<E>() -> Result<(), ScriptError<E>> {
+    BUDGET.with_borrow(|budget| match budget {
+        Some(budget) => budget.check(),
+        None => Ok(()),
+    })
+}
+
+/* Spends one unit of fuel for an expression evaluation */
+pub(crate) fn charge<E>() -> Result<(), ScriptError<E>> {
+    BUDGET.with_borrow_mut(|budget| {
+        let Some(budget) = budget else {
+            return Ok(());
+        };
+        match &mut budget.fuel {
+            Some(0) => return Err(ScriptError::OutOfFuel),
+            Some(fuel) => *fuel -= 1,
+            None => (),
+        }
+        budget.spent += 1;
+        budget.check()
+    })
+}
+
+/* Fails when the source of the current run is longer than allowed */
+pub(crate) fn check_source_len(len: usize) -> Result<(), LimitError> {
+    match BUDGET.with_borrow(|budget| budget.as_ref()?.max_source_len) {
+        Some(max) if len > max => Err(LimitError::SourceTooLong(max)),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn max_tokens() -> Option<usize> {
+    BUDGET.with_borrow(|budget| budget.as_ref()?.max_tokens)
+}
+
+pub(crate) fn max_parse_depth() -> Option<usize> {
+    BUDGET.with_borrow(|budget| budget.as_ref()?.max_parse_depth)
+}
+
+/* Enters a nested evaluation, the depth is given back when the guard is dropped */
+pub(crate) fn descend<E>() -> Result<DepthGuard, ScriptError<E>> {
+    BUDGET.with_borrow_mut(|budget| {
+        let Some(budget) = budget else {
+            return Ok(DepthGuard);
+        };
+        match budget.max_eval_depth {
+            Some(max) if budget.eval_depth >= max => {
+                Err(ScriptError::Limit(LimitError::EvaluationTooDeep(max)))
+            }
+            _ => {
+                budget.eval_depth += 1;
+                Ok(DepthGuard)
+            }
+        }
+    })
+}
+
+pub(crate) struct DepthGuard;
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        BUDGET.with_borrow_mut(|budget| {
+            if let Some(budget) = budget {
+                budget.eval_depth = budget.eval_depth.saturating_sub(1);
+            }
+        });
+    }
+}
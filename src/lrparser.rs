@@ -1,258 +1,631 @@
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    fmt::Display,
-    rc::Rc,
-};
+use std::fmt::Display;
+
+use ry_script_lr::{Compressed, Item, Rule};
+
+pub use ry_script_lr::TransitionAction;
 
 use super::{
-    error::{GrammarError, ParseError},
+    error::{GrammarError, ParseError, RuntimeError, ScriptError, SyntaxError},
     grammar::{Grammar, GrammarSet, Symbol},
-    token::ParserToken,
+    table::{fingerprint, ParseTable},
+    token::{ParserToken, Span},
 };
 
-pub enum TransitionAction {
-    Shift(usize),
-    Reduce(usize),
-    Goto(usize),
-    Accept,
+struct ItemDisplay<'a, T: ParserToken<T>>(&'a Grammar<T>, usize);
+
+impl<'a, T: ParserToken<T>> ItemDisplay<'a, T> {
+    fn new(item: &Item, grammar_set: &'a GrammarSet<T>) -> ItemDisplay<'a, T> {
+        ItemDisplay(&grammar_set.grammars[item.rule_idx], item.rval_idx)
+    }
 }
 
-impl Display for TransitionAction {
+impl<T: ParserToken<T>> Display for ItemDisplay<'_, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            TransitionAction::Shift(s) => write!(f, "shift {}", s)?,
-            TransitionAction::Reduce(r) => write!(f, "reduce {}", r)?,
-            TransitionAction::Goto(r) => write!(f, "goto {}", r)?,
-            TransitionAction::Accept => write!(f, "accept")?,
+        let ItemDisplay(grammar, rval_idx) = self;
+        write!(f, "{} -> ", grammar.lval)?;
+        for (i, rval) in grammar.rvals.iter().enumerate() {
+            if i == *rval_idx {
+                write!(f, "• ")?;
+            }
+            write!(f, "{} ", rval)?
+        }
+        if *rval_idx == grammar.rvals.len() {
+            write!(f, "• ")?;
         }
         Ok(())
     }
 }
 
-#[derive(Debug)]
-struct ItemSet<T: ParserToken<T>> {
-    items: Vec<Kernel<T>>,
+/*
+Row displacement compressed action/goto table indexed by state and symbol id.
+The action of (state, symbol) lives in slot base[state] + symbol when the slot is owned by that state,
+otherwise a state falls back to its default reduction on terminals.
+*/
+struct ActionTable {
+    terminal_count: usize,
+    base: Vec<usize>,
+    slots: Vec<Option<(usize, TransitionAction)>>,
+    default_reduce: Vec<Option<usize>>,
 }
 
-/* TODO: Should really use a hashset instead (or maybe a BTreeSet?)
- * Issue 1. cannot add while itering
- * Issue 2. needs to impl eq and hash for kernel
- */
-impl<T: ParserToken<T>> ItemSet<T> {
-    fn has(&self, kernel: &Kernel<T>) -> bool {
-        match self.items.iter().find(|&k| k == kernel) {
-            Some(_) => true,
-            None => false,
+impl ActionTable {
+    fn new(
+        compressed: Compressed,
+        default_reduce: Vec<Option<usize>>,
+        terminal_count: usize,
+    ) -> ActionTable {
+        ActionTable {
+            terminal_count,
+            base: compressed.base,
+            slots: compressed.slots,
+            default_reduce,
         }
     }
-}
 
-impl<T: ParserToken<T>> PartialEq for ItemSet<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.items.iter().filter(|&k| !other.has(k)).count() == 0
+    fn state_count(&self) -> usize {
+        self.base.len()
     }
-}
 
-impl<T: ParserToken<T>> Display for ItemSet<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} items", self.items.len())?;
-        for (i, kernel) in self.items.iter().enumerate() {
-            write!(f, "\n\t{}. {}", i + 1, kernel)?;
+    /* The action stored for the symbol, leaving out the default reduction */
+    fn explicit(&self, state: usize, symbol: usize) -> Option<TransitionAction> {
+        match self.slots.get(self.base[state] + symbol) {
+            Some(&Some((owner, action))) if owner == state => Some(action),
+            _ => None,
         }
-        Ok(())
     }
-}
 
-#[derive(Debug, PartialEq, Eq)]
-struct Kernel<T: ParserToken<T>> {
-    grammar: Rc<Grammar<T>>,
-    rval_idx: usize,
-}
-
-impl<T: ParserToken<T>> Kernel<T> {
-    fn current_symbol(&self) -> Option<Rc<Symbol<T>>> {
-        match self.grammar.rvals.get(self.rval_idx) {
+    fn get(&self, state: usize, symbol: usize) -> Option<TransitionAction> {
+        match self.explicit(state, symbol) {
+            Some(action) => Some(action),
+            None if symbol < self.terminal_count => {
+                self.default_reduce[state].map(TransitionAction::Reduce)
+            }
             None => None,
-            Some(val) => Some(Rc::clone(val)),
         }
     }
 }
 
-impl<T: ParserToken<T>> Display for Kernel<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} -> ", self.grammar.lval)?;
-        for (i, rval) in self.grammar.rvals.iter().enumerate() {
-            if i == self.rval_idx {
-                write!(f, "• ")?;
-            }
-            write!(f, "{} ", rval)?
-        }
-        if self.rval_idx == self.grammar.rvals.len() {
-            write!(f, "• ")?;
-        }
-        Ok(())
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Text,
+    Markdown,
+    Csv,
+    Html,
+}
+
+/* Rule texts and the compressed parse table generated by the grammar! macro, symbols are numbered as GrammarSet numbers them */
+pub struct CompiledGrammar {
+    pub rules: &'static [&'static str],
+    /* Symbol names by id */
+    pub symbols: &'static [&'static str],
+    pub base: &'static [usize],
+    pub slots: &'static [Option<(usize, TransitionAction)>],
+    pub default_reduce: &'static [Option<usize>],
+    pub conflicts: &'static [usize],
 }
 
+/* Part of the fingerprint of saved tables, changed whenever the table construction changes */
+const TABLE_ALGORITHM: &str = "lr0 shift-preference default-reduce 1";
+
 pub struct LRParser<T: ParserToken<T>> {
     pub grammar_set: GrammarSet<T>,
-    table: Vec<HashMap<Rc<Symbol<T>>, TransitionAction>>,
+    table: ActionTable,
+    /* States where more than one action competed for a symbol */
+    conflicts: Vec<usize>,
 }
 
 impl<T: ParserToken<T>> LRParser<T> {
     pub fn lr0(grammar_set: GrammarSet<T>) -> Result<LRParser<T>, GrammarError> {
-        let mut transition_table = Vec::new();
-        let starter_grammar = match grammar_set.grammars.get(0) {
-            None => {
-                return Err(GrammarError::Error(
-                    "Grammar set does not have a starter grammar",
-                ))
-            }
-            Some(rule) => rule,
-        };
-        let starter_item_set = ItemSet {
-            items: vec![Kernel {
-                grammar: Rc::clone(starter_grammar),
-                rval_idx: 0,
-            }],
+        if grammar_set.grammars.is_empty() {
+            return Err(GrammarError::MissingStartRule);
+        }
+        let rules = numbered_rules(&grammar_set);
+        let eof = grammar_set.terminal_id(grammar_set.eof);
+        let symbol_count = grammar_set.symbols.len();
+        let terminal_count = grammar_set.terminal_count;
+        let automaton = ry_script_lr::lr0(&rules, symbol_count, terminal_count, eof);
+        let compressed = ry_script_lr::compress(&automaton.rows);
+        let table = ActionTable::new(compressed, automaton.default_reduce, terminal_count);
+        Ok(LRParser {
+            grammar_set,
+            table,
+            conflicts: automaton.conflicts,
+        })
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.table.state_count()
+    }
+
+    /* The compiled table is used as it is once its symbols are known to have the same ids here */
+    pub fn from_compiled(
+        grammar_set: GrammarSet<T>,
+        compiled: &CompiledGrammar,
+    ) -> Result<LRParser<T>, GrammarError> {
+        let same_ids = compiled.symbols.iter().enumerate().all(|(id, name)| {
+            let symbol = grammar_set.get_symbol(name);
+            symbol.and_then(|symbol| grammar_set.symbol_id(&symbol)) == Some(id)
+        });
+        if grammar_set.grammars.len() != compiled.rules.len()
+            || grammar_set.symbols.len() != compiled.symbols.len()
+            || !same_ids
+        {
+            return Err(GrammarError::CompiledGrammarMismatch);
+        }
+        let state_count = compiled.base.len();
+        if compiled.default_reduce.len() != state_count {
+            return Err(GrammarError::InvalidParseTable(
+                "a default reduction for every state".to_string(),
+            ));
+        }
+        for (slot, entry) in compiled.slots.iter().enumerate() {
+            let Some((state, action)) = *entry else {
+                continue;
+            };
+            let symbol = match compiled.base.get(state) {
+                Some(&base) if base <= slot => slot - base,
+                _ => {
+                    let message = format!("slot {} of state {} has no symbol", slot, state);
+                    return Err(GrammarError::InvalidParseTable(message));
+                }
+            };
+            check_action(&grammar_set, state_count, state, symbol, action)?;
+        }
+        check_default_reduce(&grammar_set, compiled.default_reduce)?;
+        let table = ActionTable {
+            terminal_count: grammar_set.terminal_count,
+            base: compiled.base.to_vec(),
+            slots: compiled.slots.to_vec(),
+            default_reduce: compiled.default_reduce.to_vec(),
         };
-        let mut item_sets = Vec::from([starter_item_set]);
-        let mut item_set_idx = 0;
-        while let Some(item_set) = item_sets.get_mut(item_set_idx) {
-            // populate a transition row
-            let mut transition_row = HashMap::<Rc<Symbol<T>>, TransitionAction>::new();
-            let mut new_itemsets: HashMap<Rc<Symbol<T>>, ItemSet<T>> = HashMap::new();
-            let mut i = 0;
-            while let Some(kernel) = item_set.items.get(i) {
-                match kernel.current_symbol() {
-                    Some(symbol) => {
-                        match *symbol {
-                            Symbol::Terminal(t) if t == grammar_set.eof => {
-                                // EOF - Accept
-                                transition_row.insert(symbol, TransitionAction::Accept);
-                            }
-                            Symbol::NonTerminal(_) => {
-                                // expand item sets
-                                let mut kernels: Vec<Kernel<T>> = grammar_set
-                                    .find_grammars(symbol)
-                                    .into_iter()
-                                    .map(|grammar| Kernel {
-                                        grammar,
-                                        rval_idx: 0,
-                                    })
-                                    .filter(|k| !item_set.has(k))
-                                    .collect();
-                                item_set.items.append(&mut kernels);
-                            }
-                            _ => (/* do nothing for terminal symbols */),
-                        }
+        Ok(LRParser {
+            grammar_set,
+            table,
+            conflicts: compiled.conflicts.to_vec(),
+        })
+    }
+
+    /* Loads a table saved by to_table, rejected when the grammar or the table construction has changed since */
+    pub fn from_table(
+        grammar_set: GrammarSet<T>,
+        table: &ParseTable,
+    ) -> Result<LRParser<T>, GrammarError> {
+        if table.fingerprint != table_fingerprint(&grammar_set) {
+            return Err(GrammarError::StaleParseTable);
+        }
+        if table.default_reduce.len() != table.table.len() {
+            return Err(GrammarError::InvalidParseTable(
+                "a default reduction for every state".to_string(),
+            ));
+        }
+        let rows = table
+            .table
+            .iter()
+            .map(|row| row.iter().map(|(name, action)| (name.as_str(), *action)));
+        LRParser::from_rows(grammar_set, rows, table.default_reduce.clone())
+    }
+
+    /* Every action is checked, a bad table is rejected here instead of failing a later parse */
+    fn from_rows<'a>(
+        grammar_set: GrammarSet<T>,
+        rows: impl ExactSizeIterator<Item = impl Iterator<Item = (&'a str, TransitionAction)>>,
+        default_reduce: Vec<Option<usize>>,
+    ) -> Result<LRParser<T>, GrammarError> {
+        let invalid = |message: String| Err(GrammarError::InvalidParseTable(message));
+        let state_count = rows.len();
+        let mut table = vec![];
+        for (state, row) in rows.enumerate() {
+            let mut transition_row = vec![];
+            for (name, action) in row {
+                let symbol = match grammar_set
+                    .get_symbol(name)
+                    .and_then(|symbol| grammar_set.symbol_id(&symbol))
+                {
+                    Some(symbol) => symbol,
+                    None => return invalid(format!("unknown symbol {}", name)),
+                };
+                check_action(&grammar_set, state_count, state, symbol, action)?;
+                transition_row.push((symbol, action));
+            }
+            transition_row.sort_by_key(|&(symbol, _)| symbol);
+            if let Some(pair) = transition_row
+                .windows(2)
+                .find(|pair| pair[0].0 == pair[1].0)
+            {
+                let name = &grammar_set.symbols[pair[0].0];
+                return invalid(format!("two actions on {} in state {}", name, state));
+            }
+            table.push(transition_row);
+        }
+        check_default_reduce(&grammar_set, &default_reduce)?;
+        let compressed = ry_script_lr::compress(&table);
+        let table = ActionTable::new(compressed, default_reduce, grammar_set.terminal_count);
+        Ok(LRParser {
+            grammar_set,
+            table,
+            conflicts: vec![],
+        })
+    }
+
+    pub fn to_table(&self) -> ParseTable {
+        let names: Vec<Option<&str>> = self
+            .grammar_set
+            .symbols
+            .iter()
+            .map(|symbol| self.grammar_set.symbol_name(symbol))
+            .collect();
+        let table = (0..self.state_count())
+            .map(|state| {
+                let mut row: Vec<(String, TransitionAction)> = names
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(symbol, &name)| {
+                        Some((name?.to_string(), self.table.explicit(state, symbol)?))
+                    })
+                    .collect();
+                row.sort_by(|a, b| a.0.cmp(&b.0));
+                row
+            })
+            .collect();
+        ParseTable {
+            fingerprint: table_fingerprint(&self.grammar_set),
+            rules: self
+                .grammar_set
+                .grammars
+                .iter()
+                .map(|g| format!("{}", g))
+                .collect(),
+            table,
+            default_reduce: self.table.default_reduce.clone(),
+        }
+    }
+
+    pub fn conflicts(&self) -> &[usize] {
+        &self.conflicts
+    }
+
+    /* Item set of every state, recovered by following the shift/goto edges of the table from state 0 */
+    fn item_sets(&self) -> Vec<Vec<Item>> {
+        let rules = numbered_rules(&self.grammar_set);
+        let rules_by_lval = ry_script_lr::rules_by_lval(&rules, self.grammar_set.symbols.len());
+        let mut cores: Vec<Option<Vec<Item>>> = vec![None; self.state_count()];
+        cores[0] = Some(vec![Item {
+            rule_idx: 0,
+            rval_idx: 0,
+        }]);
+        let mut item_sets = vec![];
+        for state in 0..self.state_count() {
+            // every predecessor contributes the same core
+            let mut core = cores[state].take().unwrap_or_default();
+            core.sort_unstable();
+            core.dedup();
+            let items = Item::closure(&core, &rules, &rules_by_lval);
+            for item in &items {
+                let Some(symbol) = item.current_symbol(&rules) else {
+                    continue;
+                };
+                if let Some(TransitionAction::Shift(next) | TransitionAction::Goto(next)) =
+                    self.table.get(state, symbol)
+                {
+                    if next > state {
+                        cores[next]
+                            .get_or_insert_with(Vec::new)
+                            .push(item.advance());
                     }
-                    _ => grammar_set.terminal_symbols.iter().for_each(|(_, symbol)| {
-                        transition_row.insert(
-                            Rc::clone(symbol),
-                            TransitionAction::Reduce(kernel.grammar.rule_number),
-                        );
-                    }),
                 }
-                i += 1;
             }
-            // populate new itemsets
-            for kernel in &item_set.items {
-                match kernel.current_symbol() {
-                    Some(symbol) => match *symbol {
-                        Symbol::Terminal(t) if t == grammar_set.eof => (/* do nothing */),
-                        _ => {
-                            let new_kernel = Kernel {
-                                grammar: Rc::clone(&kernel.grammar),
-                                rval_idx: kernel.rval_idx + 1,
-                            };
-                            let new_item_set = match new_itemsets.entry(symbol) {
-                                Entry::Occupied(o) => o.into_mut(),
-                                Entry::Vacant(v) => v.insert(ItemSet { items: vec![] }),
-                            };
-                            new_item_set.items.push(new_kernel);
-                        }
-                    },
-                    None => (/* do nothing */),
+            item_sets.push(items);
+        }
+        item_sets
+    }
+
+    /* Shift and goto edges of a state as (symbol id, target state) */
+    fn edges(&self, state: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.grammar_set.symbols.len()).filter_map(move |symbol| {
+            match self.table.get(state, symbol)? {
+                TransitionAction::Shift(next) | TransitionAction::Goto(next) => {
+                    Some((symbol, next))
                 }
+                _ => None,
             }
-            #[cfg(feature = "debug_lrparser")]
-            println!("Parsed Itemset {}: {}\n", transition_table.len(), item_set);
-            // add new itemsets to vector
-            new_itemsets.into_iter().for_each(|(symbol, itemset)| {
-                let state = match item_sets.iter().position(|set| itemset == *set) {
-                    None => -1,
-                    Some(idx) => idx as i32,
-                };
-                let action_value = match state == -1 {
-                    true => item_sets.len(),
-                    false => state as usize,
-                };
-                // shift/goto
-                match *symbol {
-                    Symbol::Terminal(_) => {
-                        transition_row.insert(symbol, TransitionAction::Shift(action_value))
+        })
+    }
+
+    /* Graphviz rendering of the automaton, conflicting states are filled red */
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph LR {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        dot.push_str("    accept [shape=doublecircle];\n");
+        for (state, items) in self.item_sets().iter().enumerate() {
+            let mut label = format!("{}\\l", state);
+            for item in items {
+                let item = format!("{}", ItemDisplay::new(item, &self.grammar_set));
+                label.push_str(&dot_escape(&item));
+                label.push_str("\\l");
+            }
+            let style = match self.conflicts.contains(&state) {
+                true => ", style=filled, fillcolor=\"#ffb3b3\", color=red",
+                false => "",
+            };
+            dot.push_str(&format!("    s{} [label=\"{}\"{}];\n", state, label, style));
+        }
+        for state in 0..self.state_count() {
+            for (symbol, next) in self.edges(state) {
+                let symbol = dot_escape(&format!("{}", self.grammar_set.symbols[symbol]));
+                dot.push_str(&format!(
+                    "    s{} -> s{} [label=\"{}\"];\n",
+                    state, next, symbol
+                ));
+            }
+            for symbol in 0..self.grammar_set.terminal_count {
+                if self.table.get(state, symbol) == Some(TransitionAction::Accept) {
+                    let symbol = dot_escape(&format!("{}", self.grammar_set.symbols[symbol]));
+                    dot.push_str(&format!(
+                        "    s{} -> accept [label=\"{}\"];\n",
+                        state, symbol
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /* Header row and one row per state, columns in symbol id order */
+    fn table_cells(&self) -> Vec<Vec<String>> {
+        let header = std::iter::once("state".to_string())
+            .chain(self.grammar_set.symbols.iter().map(|s| format!("{}", s)));
+        let mut cells = vec![header.collect()];
+        for state in 0..self.state_count() {
+            let actions = (0..self.grammar_set.symbols.len()).map(|symbol| {
+                match self.table.get(state, symbol) {
+                    Some(action) => format!("{}", action),
+                    None => String::new(),
+                }
+            });
+            cells.push(std::iter::once(state.to_string()).chain(actions).collect());
+        }
+        cells
+    }
+
+    pub fn dump_table(&self, format: TableFormat) -> String {
+        let cells = self.table_cells();
+        let mut text = String::new();
+        match format {
+            TableFormat::Text => {
+                let widths: Vec<usize> = (0..cells[0].len())
+                    .map(|col| {
+                        cells
+                            .iter()
+                            .map(|row| row[col].chars().count())
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                for row in &cells {
+                    for (cell, width) in row.iter().zip(&widths) {
+                        text.push_str(&format!("| {:<width$} ", cell, width = width));
                     }
-                    Symbol::NonTerminal(_) => {
-                        transition_row.insert(symbol, TransitionAction::Goto(action_value))
+                    text.push_str("|\n");
+                }
+            }
+            TableFormat::Markdown => {
+                for (i, row) in cells.iter().enumerate() {
+                    for cell in row {
+                        text.push_str(&format!("| {} ", cell.replace('|', "\\|")));
                     }
-                };
+                    text.push_str("|\n");
+                    if i == 0 {
+                        text.push_str(&"| --- ".repeat(row.len()));
+                        text.push_str("|\n");
+                    }
+                }
+            }
+            TableFormat::Csv => {
+                for row in &cells {
+                    let row: Vec<String> = row.iter().map(|cell| csv_escape(cell)).collect();
+                    text.push_str(&row.join(","));
+                    text.push('\n');
+                }
+            }
+            TableFormat::Html => {
+                text.push_str("<table>\n");
+                for (i, row) in cells.iter().enumerate() {
+                    let tag = if i == 0 { "th" } else { "td" };
+                    text.push_str("  <tr>");
+                    for cell in row {
+                        text.push_str(&format!("<{}>{}</{}>", tag, html_escape(cell), tag));
+                    }
+                    text.push_str("</tr>\n");
+                }
+                text.push_str("</table>\n");
+            }
+        }
+        text
+    }
 
-                // add brand new itemset
-                if state == -1 {
-                    item_sets.push(itemset);
+    /* Items of every state with the closure, conflicting states are marked */
+    pub fn dump_item_sets(&self) -> String {
+        let mut text = String::new();
+        for (state, items) in self.item_sets().iter().enumerate() {
+            match self.conflicts.contains(&state) {
+                true => text.push_str(&format!("State {} (conflict)\n", state)),
+                false => text.push_str(&format!("State {}\n", state)),
+            }
+            for item in items {
+                let item = ItemDisplay::new(item, &self.grammar_set);
+                text.push_str(&format!("    {}\n", item));
+            }
+        }
+        text
+    }
+
+    /* Terminals that have an action in the state, in symbol id order */
+    pub fn expected_terminals(&self, state: usize) -> Vec<&Symbol<T>> {
+        (0..self.grammar_set.terminal_count)
+            .filter(|&symbol| self.table.get(state, symbol).is_some())
+            .map(|symbol| &*self.grammar_set.symbols[symbol])
+            .collect()
+    }
+
+    /* Terminals that can follow the symbols, None when the symbols are not a valid prefix */
+    pub fn expected_after(&self, symbols: &[usize]) -> Option<Vec<&Symbol<T>>> {
+        let mut stack = vec![0];
+        for &symbol in symbols {
+            if !self.advance(&mut stack, symbol) {
+                return None;
+            }
+        }
+        let expected = (0..self.grammar_set.terminal_count)
+            .filter(|&symbol| self.advance(&mut stack.clone(), symbol))
+            .map(|symbol| &*self.grammar_set.symbols[symbol])
+            .collect();
+        Some(expected)
+    }
+
+    /* Reduces until the terminal is shifted or accepted, false if the state stack rejects it */
+    fn advance(&self, stack: &mut Vec<usize>, symbol: usize) -> bool {
+        loop {
+            let state = *stack.last().expect("the start state");
+            match self.table.get(state, symbol) {
+                Some(TransitionAction::Shift(next_state)) => {
+                    stack.push(next_state);
+                    return true;
                 }
-            });
-            transition_table.push(transition_row);
-            item_set_idx += 1;
+                Some(TransitionAction::Accept) => return true,
+                Some(TransitionAction::Reduce(rule_number)) => {
+                    let grammar = match self.grammar_set.grammars.get(rule_number - 1) {
+                        Some(grammar) if grammar.rvals.len() < stack.len() => grammar,
+                        _ => return false,
+                    };
+                    stack.truncate(stack.len() - grammar.rvals.len());
+                    let state = *stack.last().expect("the start state");
+                    match self.table.get(state, grammar.lval_id) {
+                        Some(TransitionAction::Goto(next_state)) => stack.push(next_state),
+                        _ => return false,
+                    }
+                }
+                _ => return false,
+            }
         }
-        Ok(LRParser {
-            grammar_set,
-            table: transition_table,
-        })
     }
 
-    pub fn get_action(
+    /* Maps a missing action to a syntax error at the lookahead */
+    pub(crate) fn action_at<E: RuntimeError>(
         &self,
         state: usize,
-        symbol: &Symbol<T>,
-    ) -> Result<&TransitionAction, ParseError> {
-        match self.table.get(state) {
-            Some(row) => match row.get(symbol) {
-                Some(action) => Ok(action),
-                None => return Err(ParseError::UnexpectedSymbol(format!("{}", symbol))),
+        symbol: usize,
+        span: Span,
+    ) -> Result<TransitionAction, ScriptError<E>> {
+        match self.get_action(state, symbol) {
+            Ok(action) => Ok(action),
+            Err(ParseError::UnexpectedSymbol(found)) => {
+                let expected = self.expected_terminals(state);
+                let expected = expected.iter().map(|s| s.to_string()).collect();
+                Err(SyntaxError::UnexpectedToken(found, span, expected).into())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn get_action(&self, state: usize, symbol: usize) -> Result<TransitionAction, ParseError> {
+        if state >= self.state_count() {
+            return Err(ParseError::StateDoesNotExist(state));
+        }
+        match self.table.get(state, symbol) {
+            Some(action) => Ok(action),
+            None => match self.grammar_set.symbols.get(symbol) {
+                Some(symbol) => Err(ParseError::UnexpectedSymbol(format!("{}", symbol))),
+                None => Err(ParseError::UnexpectedSymbol(format!("#{}", symbol))),
             },
-            None => return Err(ParseError::StateDoesNotExist(state)),
         }
     }
 }
 
-impl<T: ParserToken<T>> Display for LRParser<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for terminal_symbol in self.grammar_set.terminal_symbols.values() {
-            write!(f, "| {:<10} ", format!("{}", terminal_symbol))?;
+fn table_fingerprint<T: ParserToken<T>>(grammar_set: &GrammarSet<T>) -> u64 {
+    let grammar = grammar_set.fingerprint().to_string();
+    fingerprint([grammar.as_str(), TABLE_ALGORITHM].into_iter())
+}
+
+fn numbered_rules<T: ParserToken<T>>(grammar_set: &GrammarSet<T>) -> Vec<Rule> {
+    let rules = grammar_set.grammars.iter().map(|grammar| Rule {
+        lval: grammar.lval_id,
+        rvals: grammar.rval_ids.clone(),
+    });
+    rules.collect()
+}
+
+/* Rejects an action that cannot come from a table of this grammar */
+fn check_action<T: ParserToken<T>>(
+    grammar_set: &GrammarSet<T>,
+    state_count: usize,
+    state: usize,
+    symbol: usize,
+    action: TransitionAction,
+) -> Result<(), GrammarError> {
+    let invalid = |message: String| Err(GrammarError::InvalidParseTable(message));
+    let name = match grammar_set.symbols.get(symbol) {
+        Some(symbol) => symbol,
+        None => return invalid(format!("unknown symbol #{}", symbol)),
+    };
+    let terminal = symbol < grammar_set.terminal_count;
+    match action {
+        TransitionAction::Shift(next) | TransitionAction::Goto(next) if next >= state_count => {
+            invalid(format!("{} in state {} has no target", action, state))
         }
-        for non_terminal_symbol in self.grammar_set.non_terminal_symbols.values() {
-            write!(f, "| {:<10} ", format!("{}", non_terminal_symbol))?;
+        TransitionAction::Reduce(rule) if rule == 0 || rule > grammar_set.grammars.len() => {
+            invalid(format!("{} in state {} has no rule", action, state))
         }
-        writeln!(f)?;
-        for state in &self.table {
-            for terminal_symbol in self.grammar_set.terminal_symbols.values() {
-                if let Some(action) = state.get(terminal_symbol) {
-                    write!(f, "| {:<10} ", format!("{}", action))?;
-                } else {
-                    write!(f, "| {:<10} ", "")?;
-                }
-            }
-            for non_terminal_symbol in self.grammar_set.non_terminal_symbols.values() {
-                if let Some(action) = state.get(non_terminal_symbol) {
-                    write!(f, "| {:<10} ", format!("{}", action))?;
-                } else {
-                    write!(f, "| {:<10} ", "")?;
-                }
-            }
-            writeln!(f)?;
+        TransitionAction::Shift(_) | TransitionAction::Reduce(_) | TransitionAction::Accept
+            if !terminal =>
+        {
+            invalid(format!("{} on non-terminal {}", action, name))
         }
-        Ok(())
+        TransitionAction::Goto(_) if terminal => {
+            invalid(format!("{} on terminal {}", action, name))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_default_reduce<T: ParserToken<T>>(
+    grammar_set: &GrammarSet<T>,
+    default_reduce: &[Option<usize>],
+) -> Result<(), GrammarError> {
+    let rule_count = grammar_set.grammars.len();
+    match default_reduce
+        .iter()
+        .flatten()
+        .find(|&&rule| rule == 0 || rule > rule_count)
+    {
+        Some(rule) => Err(GrammarError::InvalidParseTable(format!(
+            "default reduce {} has no rule",
+            rule
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn csv_escape(text: &str) -> String {
+    match text.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<T: ParserToken<T>> Display for LRParser<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.dump_table(TableFormat::Text))
     }
 }
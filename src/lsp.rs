@@ -0,0 +1,389 @@
+/*
+Language server speaking LSP over stdio for a language built on a ScriptRunner
+*/
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use super::ast::RuntimeValue;
+use super::diagnostic::{Diagnostic, Severity};
+use super::error::RuntimeError;
+use super::grammar::Symbol;
+use super::runner::ScriptRunner;
+use super::token::{ParserToken, Tokens};
+
+/* Semantic token types, a token type is its index in the legend sent on initialize */
+const TOKEN_TYPES: [&str; 5] = ["keyword", "operator", "variable", "number", "string"];
+
+/* CompletionItemKind values of the protocol */
+const KEYWORD_ITEM: u32 = 14;
+const OPERATOR_ITEM: u32 = 24;
+
+pub struct LanguageServer<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    runner: ScriptRunner<ENV, T, R, E>,
+    /* Creates the environment documents are run in for runtime diagnostics, None only checks syntax */
+    environment: Option<Box<dyn Fn() -> ENV>>,
+    /* Open documents by uri */
+    documents: HashMap<String, String>,
+}
+
+impl<
+        ENV: 'static,
+        T: ParserToken<T> + 'static,
+        R: RuntimeValue<T> + 'static,
+        E: RuntimeError + 'static,
+    > LanguageServer<ENV, T, R, E>
+{
+    pub fn new(runner: ScriptRunner<ENV, T, R, E>) -> LanguageServer<ENV, T, R, E> {
+        LanguageServer {
+            runner,
+            environment: None,
+            documents: HashMap::new(),
+        }
+    }
+
+    /* Runs every document after it changed, runner limits apply to these runs */
+    pub fn environment(
+        mut self,
+        environment: impl Fn() -> ENV + 'static,
+    ) -> LanguageServer<ENV, T, R, E> {
+        self.environment = Some(Box::new(environment));
+        self
+    }
+
+    pub fn serve_stdio(&mut self) -> io::Result<()> {
+        self.serve(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /* Answers messages until the client sends exit or closes the input */
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        while let Some(body) = read_message(&mut input)? {
+            let replies = match serde_json::from_slice::<Value>(&body) {
+                Ok(message) if message["method"] == "exit" => return Ok(()),
+                Ok(message) => self.handle(&message),
+                Err(error) => vec![failure(Value::Null, -32700, &error.to_string())],
+            };
+            for reply in replies {
+                write_message(&mut output, &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /* Responses and notifications to send back for one message */
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => {
+                return match method {
+                    "textDocument/didOpen" => {
+                        let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                        self.documents.insert(uri.to_string(), text.to_string());
+                        vec![self.publish(uri)]
+                    }
+                    /* full sync, the last change holds the whole text */
+                    "textDocument/didChange" => {
+                        let changes = params["contentChanges"].as_array();
+                        let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                        self.documents
+                            .insert(uri.to_string(), text.unwrap_or_default().to_string());
+                        vec![self.publish(uri)]
+                    }
+                    "textDocument/didClose" => {
+                        self.documents.remove(uri);
+                        vec![notification(
+                            "textDocument/publishDiagnostics",
+                            json!({ "uri": uri, "diagnostics": [] }),
+                        )]
+                    }
+                    _ => vec![],
+                };
+            }
+        };
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {},
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                },
+                "serverInfo": { "name": "ry-script" },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/semanticTokens/full" => json!({ "data": self.semantic_tokens(text) }),
+            "textDocument/completion" => {
+                let lines = LineIndex::new(text);
+                let position = &params["position"];
+                let line = position["line"].as_u64().unwrap_or_default() as usize;
+                let character = position["character"].as_u64().unwrap_or_default() as usize;
+                json!(self.completion(text, lines.offset(line, character)))
+            }
+            /* a response from the client */
+            "" => return vec![],
+            _ => return vec![failure(id, -32601, &format!("unknown method {}", method))],
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn publish(&self, uri: &str) -> Value {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let lines = LineIndex::new(text);
+        let diagnostics: Vec<_> = self
+            .diagnose(text)
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(diagnostic, &lines))
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /* Lexer errors first, then the syntax error, then what running the document reports */
+    fn diagnose(&self, text: &str) -> Vec<Diagnostic> {
+        if text.is_empty() {
+            return vec![];
+        }
+        match self.runner.lexer().parse_recovering(text) {
+            Ok((_, errors)) if !errors.is_empty() => {
+                return errors.iter().map(Diagnostic::from).collect()
+            }
+            Ok(_) => {}
+            Err(error) => return vec![Diagnostic::from(&error)],
+        }
+        if let Err(error) = self.runner.parse_tree(text) {
+            return vec![error.diagnostic()];
+        }
+        match &self.environment {
+            Some(environment) => match self.runner.run_with_warnings(&mut environment(), text) {
+                Ok((_, warnings)) => warnings,
+                Err(error) => vec![error.diagnostic()],
+            },
+            None => vec![],
+        }
+    }
+
+    /* Relative encoded tokens of the protocol, tokens spanning lines are cut at the end of the first */
+    fn semantic_tokens(&self, text: &str) -> Vec<usize> {
+        let Tokens(tokens, spans) = match self.runner.lexer().parse_recovering(text) {
+            Ok((tokens, _)) => tokens,
+            Err(_) => return vec![],
+        };
+        let lines = LineIndex::new(text);
+        let mut data = vec![];
+        let (mut last_line, mut last_start) = (0, 0);
+        for (token, span) in tokens.iter().zip(spans) {
+            let Some(token_type) = self.token_type(token.r#type) else {
+                continue;
+            };
+            let (line, start) = lines.position(span.start);
+            let end = match lines.position(span.end) {
+                (end_line, end) if end_line == line => end,
+                _ => lines.position(lines.line_end(line)).1,
+            };
+            if end == start {
+                continue;
+            }
+            let delta_start = match line == last_line {
+                true => start - last_start,
+                false => start,
+            };
+            data.extend([line - last_line, delta_start, end - start, token_type, 0]);
+            (last_line, last_start) = (line, start);
+        }
+        data
+    }
+
+    fn token_type(&self, token: T) -> Option<usize> {
+        let lexer = self.runner.lexer();
+        let special = lexer.special_token_map();
+        let map = lexer.token_map();
+        let name = match token {
+            _ if special.keywords().any(|(_, t)| t == token) => "keyword",
+            _ if special.operators().any(|(_, t)| t == token) => "operator",
+            _ if token == map.identifier => "variable",
+            _ if token == map.integer || token == map.float => "number",
+            _ if token == map.string => "string",
+            _ => return None,
+        };
+        TOKEN_TYPES.iter().position(|&t| t == name)
+    }
+
+    /* Keywords and operators the parser accepts at the offset, every keyword when the text before it does not parse */
+    fn completion(&self, text: &str, offset: usize) -> Vec<Value> {
+        let before = &text[..offset];
+        /* the word being typed is replaced by the completion */
+        let word_start = before
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| ch == '_' || ch.is_alphanumeric())
+            .last()
+            .map_or(offset, |(start, _)| start);
+        let word = &before[word_start..];
+        let expected = self.expected_at(&before[..word_start]);
+        let accepts = |token: T| match &expected {
+            Some(expected) => expected.iter().any(|s| **s == Symbol::Terminal(token)),
+            None => true,
+        };
+        let special = self.runner.lexer().special_token_map();
+        let keywords = special.keywords().map(|(name, t)| (name, t, KEYWORD_ITEM));
+        let operators = special
+            .operators()
+            .map(|(name, t)| (name, t, OPERATOR_ITEM));
+        let mut items: Vec<_> = keywords
+            .chain(operators.filter(|_| expected.is_some()))
+            .filter(|&(name, token, _)| name.starts_with(word) && accepts(token))
+            .map(|(name, _, kind)| (name, kind))
+            .collect();
+        items.sort();
+        items
+            .into_iter()
+            .map(|(name, kind)| json!({ "label": name, "kind": kind }))
+            .collect()
+    }
+
+    fn expected_at(&self, before: &str) -> Option<Vec<&Symbol<T>>> {
+        let lr_parser = self.runner.lr_parser();
+        let symbols = match before.trim().is_empty() {
+            true => vec![],
+            false => {
+                let Tokens(tokens, _) = self.runner.lexer().parse(before).ok()?;
+                /* without the eof */
+                let tokens = &tokens[..tokens.len() - 1];
+                let ids = tokens
+                    .iter()
+                    .map(|t| lr_parser.grammar_set.terminal_id(t.r#type));
+                ids.collect::<Option<Vec<_>>>()?
+            }
+        };
+        lr_parser.expected_after(&symbols)
+    }
+}
+
+/* Byte offsets to the line and UTF-16 column positions of the protocol */
+struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> LineIndex<'a> {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        let column = self.text[self.starts[line]..offset].encode_utf16().count();
+        (line, column)
+    }
+
+    /* Offset of the line break that ends the line */
+    fn line_end(&self, line: usize) -> usize {
+        self.starts
+            .get(line + 1)
+            .map_or(self.text.len(), |&next| next - 1)
+    }
+
+    /* Positions past the end of a line are clamped to it */
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(&start) = self.starts.get(line) else {
+            return self.text.len();
+        };
+        let mut column = 0;
+        for (offset, ch) in self.text[start..self.line_end(line)].char_indices() {
+            if column >= character {
+                return start + offset;
+            }
+            column += ch.len_utf16();
+        }
+        self.line_end(line)
+    }
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic, lines: &LineIndex) -> Value {
+    let span = diagnostic
+        .labels
+        .iter()
+        .find(|label| label.primary)
+        .map(|label| label.span)
+        .unwrap_or_default();
+    let (start_line, start) = lines.position(span.start);
+    let (end_line, end) = lines.position(span.end);
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let message = match &diagnostic.help {
+        Some(help) => format!("{}\nhelp: {}", diagnostic.message, help),
+        None => diagnostic.message.clone(),
+    };
+    json!({
+        "range": {
+            "start": { "line": start_line, "character": start },
+            "end": { "line": end_line, "character": end },
+        },
+        "severity": severity,
+        "code": diagnostic.code,
+        "source": "ry-script",
+        "message": message,
+    })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn failure(id: Value, code: i32, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/* Body of the next message framed by a Content-Length header, None at the end of input */
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
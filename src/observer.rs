@@ -0,0 +1,98 @@
+/*
+Hooks into lexing, parsing and reducing, attached to a ScriptRunner at runtime
+*/
+
+use std::fmt::Display;
+
+use super::grammar::{Grammar, Symbol};
+use super::token::{ParserToken, Token};
+
+/* Every event does nothing by default, implement only the ones of interest */
+#[allow(unused_variables)]
+pub trait ParseObserver<T: ParserToken<T>>: Send + Sync {
+    /* As the lexer creates the token, the input is lexed to the end before parsing starts */
+    fn token_lexed(&self, token: &Token<T>) {}
+
+    fn shift(&self, state: usize, token: &Token<T>, next_state: usize) {}
+
+    fn reduce(&self, state: usize, rule: &Grammar<T>) {}
+
+    fn goto(&self, state: usize, symbol: &Symbol<T>, next_state: usize) {}
+
+    fn accept(&self, state: usize) {}
+
+    fn error(&self, error: &dyn Display) {}
+
+    /* node is what the reducer of rule returned */
+    fn reducer_evaluated(&self, rule: &Grammar<T>, node: &dyn Display) {}
+}
+
+/* Forwards events to the log crate, at trace level except for errors */
+#[cfg(feature = "log")]
+pub struct LogObserver;
+
+#[cfg(feature = "log")]
+impl<T: ParserToken<T>> ParseObserver<T> for LogObserver {
+    fn token_lexed(&self, token: &Token<T>) {
+        log::trace!("token {:?} {:?}", token.r#type, token.value);
+    }
+
+    fn shift(&self, state: usize, token: &Token<T>, next_state: usize) {
+        log::trace!("[{}] shift {:?} -> {}", state, token.r#type, next_state);
+    }
+
+    fn reduce(&self, state: usize, rule: &Grammar<T>) {
+        log::trace!("[{}] reduce {}. {}", state, rule.rule_number, rule);
+    }
+
+    fn goto(&self, state: usize, symbol: &Symbol<T>, next_state: usize) {
+        log::trace!("[{}] goto {} -> {}", state, symbol, next_state);
+    }
+
+    fn accept(&self, state: usize) {
+        log::trace!("[{}] accept", state);
+    }
+
+    fn error(&self, error: &dyn Display) {
+        log::error!("{}", error);
+    }
+
+    fn reducer_evaluated(&self, rule: &Grammar<T>, node: &dyn Display) {
+        log::trace!("reducer {} returned {}", rule.rule_number, node);
+    }
+}
+
+/* Forwards events to the tracing crate, at trace level except for errors */
+#[cfg(feature = "tracing")]
+pub struct TracingObserver;
+
+#[cfg(feature = "tracing")]
+impl<T: ParserToken<T>> ParseObserver<T> for TracingObserver {
+    fn token_lexed(&self, token: &Token<T>) {
+        tracing::trace!(r#type = ?token.r#type, value = %token.value, "token");
+    }
+
+    fn shift(&self, state: usize, token: &Token<T>, next_state: usize) {
+        tracing::trace!(state, r#type = ?token.r#type, next_state, "shift");
+    }
+
+    fn reduce(&self, state: usize, rule: &Grammar<T>) {
+        tracing::trace!(state, rule = rule.rule_number, grammar = %rule, "reduce");
+    }
+
+    fn goto(&self, state: usize, symbol: &Symbol<T>, next_state: usize) {
+        tracing::trace!(state, symbol = %symbol, next_state, "goto");
+    }
+
+    fn accept(&self, state: usize) {
+        tracing::trace!(state, "accept");
+    }
+
+    fn error(&self, error: &dyn Display) {
+        tracing::error!(error = %error, "script error");
+    }
+
+    fn reducer_evaluated(&self, rule: &Grammar<T>, node: &dyn Display) {
+        tracing::trace!(rule = rule.rule_number, node = %node, "reducer evaluated");
+    }
+}
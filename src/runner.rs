@@ -1,22 +1,70 @@
-use std::collections::VecDeque;
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
 
-use super::ast::{ASTNode, ExpressionReducer, RuntimeValue};
-use super::error::{ParseError, RuntimeError, SyntaxError};
-use super::grammar::{GrammarSet, Symbol, TerminalSymbolDef};
+use super::ast::{ASTNode, Action, ExpressionReducer, RuntimeValue};
+use super::coverage::Coverage;
+use super::debugger::{DebugSession, Debugger};
+use super::diagnostic::{self, Diagnostic, WarningSink};
+use super::error::{
+    GrammarError, LimitError, ParseError, ReducerError, Result, RuntimeError, SyntaxError,
+    TraceFrame,
+};
+use super::grammar::{GrammarSet, TerminalSymbolDef};
 use super::lexer::Lexer;
-use super::lrparser::{LRParser, TransitionAction};
-use super::token::{LexerTokenMap, ParserToken, SpecialTokenMap, Tokens};
+use super::limits::{self, ExecutionLimits};
+use super::lrparser::{CompiledGrammar, LRParser, TransitionAction};
+use super::observer::ParseObserver;
+use super::syntax::{self, SyntaxTree};
+use super::table::ParseTable;
+use super::token::{LexerTokenMap, ParserToken, Span, SpecialTokenMap, TextEdit, Token, Tokens};
 
 pub struct ScriptRunner<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
     lexer: Lexer<T>,
     lr_parser: LRParser<T>,
     reducer: Vec<ExpressionReducer<ENV, T, R, E>>,
+    observer: Option<Arc<dyn ParseObserver<T>>>,
+    coverage: Option<Arc<Coverage>>,
+    limits: ExecutionLimits,
 }
 
-pub struct GrammarRule<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError>(
-    pub &'static str,
-    pub ExpressionReducer<ENV, T, R, E>,
-);
+/* Hook applied to every reduced AST node with its rule number and source span */
+type Instrument<'a, ENV, T, R, E> =
+    dyn Fn(ASTNode<ENV, T, R, E>, usize, Span) -> ASTNode<ENV, T, R, E> + 'a;
+
+/* Grammar rule text, its reducer and the labels that the reducer takes by name */
+pub struct GrammarRule<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    pub text: &'static str,
+    pub reducer: ExpressionReducer<ENV, T, R, E>,
+    labels: &'static [&'static str],
+}
+
+/* Constructs a rule as GrammarRule(text, reducer) */
+#[allow(non_snake_case)]
+pub fn GrammarRule<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError>(
+    text: &'static str,
+    reducer: ExpressionReducer<ENV, T, R, E>,
+) -> GrammarRule<ENV, T, R, E> {
+    GrammarRule {
+        text,
+        reducer,
+        labels: &[],
+    }
+}
+
+impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> GrammarRule<ENV, T, R, E> {
+    /* Declares the labels the reducer takes, ScriptRunner::new fails if the rule text does not define one */
+    pub fn labels(self, labels: &'static [&'static str]) -> Self {
+        GrammarRule { labels, ..self }
+    }
+}
+
+/* Parse tables that were built ahead of time */
+enum Prebuilt<'a> {
+    Compiled(&'a CompiledGrammar),
+    Table(&'a ParseTable),
+}
 
 impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ScriptRunner<ENV, T, R, E> {
     pub fn new(
@@ -24,7 +72,51 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ScriptRunner<E
         token_map: LexerTokenMap<T>,
         operator: &[TerminalSymbolDef<T>],
         keyword: &[TerminalSymbolDef<T>],
-    ) -> super::error::Result<ScriptRunner<ENV, T, R, E>, E> {
+    ) -> Result<ScriptRunner<ENV, T, R, E>, E> {
+        Self::build(grammars, token_map, operator, keyword, None)
+    }
+
+    /* Uses the parse table built at compile time by grammar! instead of running LRParser::lr0 */
+    pub fn with_compiled_grammar(
+        compiled: &CompiledGrammar,
+        grammars: Vec<GrammarRule<ENV, T, R, E>>,
+        token_map: LexerTokenMap<T>,
+        operator: &[TerminalSymbolDef<T>],
+        keyword: &[TerminalSymbolDef<T>],
+    ) -> Result<ScriptRunner<ENV, T, R, E>, E> {
+        Self::build(
+            grammars,
+            token_map,
+            operator,
+            keyword,
+            Some(Prebuilt::Compiled(compiled)),
+        )
+    }
+
+    /* Uses a parse table saved by ScriptRunner::parse_table, fails if the grammar has changed */
+    pub fn with_parse_table(
+        table: &ParseTable,
+        grammars: Vec<GrammarRule<ENV, T, R, E>>,
+        token_map: LexerTokenMap<T>,
+        operator: &[TerminalSymbolDef<T>],
+        keyword: &[TerminalSymbolDef<T>],
+    ) -> Result<ScriptRunner<ENV, T, R, E>, E> {
+        Self::build(
+            grammars,
+            token_map,
+            operator,
+            keyword,
+            Some(Prebuilt::Table(table)),
+        )
+    }
+
+    fn build(
+        grammars: Vec<GrammarRule<ENV, T, R, E>>,
+        token_map: LexerTokenMap<T>,
+        operator: &[TerminalSymbolDef<T>],
+        keyword: &[TerminalSymbolDef<T>],
+        prebuilt: Option<Prebuilt>,
+    ) -> Result<ScriptRunner<ENV, T, R, E>, E> {
         let mut terminal_symbols = vec![
             TerminalSymbolDef("id", token_map.identifier),
             TerminalSymbolDef("str", token_map.string),
@@ -32,6 +124,9 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ScriptRunner<E
             TerminalSymbolDef("float", token_map.float),
             TerminalSymbolDef("EOF", token_map.eof),
         ];
+        if let Some(error) = token_map.error {
+            terminal_symbols.push(TerminalSymbolDef("error", error));
+        }
         for &symbol in operator {
             terminal_symbols.push(symbol);
         }
@@ -39,108 +134,303 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ScriptRunner<E
             terminal_symbols.push(symbol);
         }
         let grammar_set = GrammarSet::new(&grammars, &terminal_symbols, token_map.eof)?;
-        let lr_parser = LRParser::lr0(grammar_set)?;
-        #[cfg(feature = "debug_lrparser")]
-        println!("{}", lr_parser);
+        for (rule, grammar) in grammars.iter().zip(&grammar_set.grammars) {
+            if let Some(&label) = rule
+                .labels
+                .iter()
+                .find(|&&label| !grammar.labels.contains(&Some(label)))
+            {
+                return Err(ReducerError::UnknownLabel(label).into());
+            }
+        }
+        let lr_parser = match prebuilt {
+            Some(Prebuilt::Table(table)) => LRParser::from_table(grammar_set, table)?,
+            Some(Prebuilt::Compiled(compiled)) => {
+                if !grammars
+                    .iter()
+                    .map(|g| g.text)
+                    .eq(compiled.rules.iter().copied())
+                {
+                    return Err(GrammarError::CompiledGrammarMismatch.into());
+                }
+                LRParser::from_compiled(grammar_set, compiled)?
+            }
+            None => LRParser::lr0(grammar_set)?,
+        };
         let special_token_map = SpecialTokenMap::new(operator, keyword);
         Ok(ScriptRunner {
             lexer: Lexer::new(token_map, special_token_map),
             lr_parser,
-            reducer: grammars.into_iter().map(|g| g.1).collect(),
+            reducer: grammars.into_iter().map(|g| g.reducer).collect(),
+            observer: None,
+            coverage: None,
+            limits: ExecutionLimits::default(),
         })
     }
 
-    pub fn run(&mut self, env: &mut ENV, input: &str) -> super::error::Result<R, E> {
-        let tokens = self.lexer.parse(input)?;
-        let execution_result = match self.lr_parse(tokens)?.evaluate(env)? {
+    pub fn parse_table(&self) -> ParseTable {
+        self.lr_parser.to_table()
+    }
+
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn ParseObserver<T>>>) {
+        self.observer = observer;
+    }
+
+    /* Counts reduced rules and entered states of every run until it is set to None */
+    pub fn set_coverage(&mut self, coverage: Option<Arc<Coverage>>) {
+        self.coverage = coverage;
+    }
+
+    fn notify(&self, event: impl FnOnce(&dyn ParseObserver<T>)) {
+        if let Some(observer) = &self.observer {
+            event(observer.as_ref());
+        }
+    }
+
+    pub fn lexer(&self) -> &Lexer<T> {
+        &self.lexer
+    }
+
+    pub fn lr_parser(&self) -> &LRParser<T> {
+        &self.lr_parser
+    }
+
+    /* Limits applied to every run that is not given its own */
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn run(&self, env: &mut ENV, input: &str) -> Result<R, E> {
+        self.run_with_limits(env, input, &self.limits)
+    }
+
+    pub fn run_with_limits(
+        &self,
+        env: &mut ENV,
+        input: &str,
+        limits: &ExecutionLimits,
+    ) -> Result<R, E> {
+        let result = self.execute(env, input, None, limits);
+        if let Err(error) = &result {
+            self.notify(|o| o.error(&error.message()));
+        }
+        result
+    }
+
+    /* Runs the script and returns the warnings reducers and expressions reported along with the value,
+    the warnings of a run that fails are dropped with it */
+    pub fn run_with_warnings(&self, env: &mut ENV, input: &str) -> Result<(R, Vec<Diagnostic>), E> {
+        let sink = WarningSink::collect();
+        let value = self.run(env, input)?;
+        Ok((value, sink.finish()))
+    }
+
+    /* Parses into a syntax tree without running any reducer, for editors that reparse after every edit */
+    pub fn parse_tree(&self, input: &str) -> Result<SyntaxTree<T>, E> {
+        let _budget = self.limits.enter();
+        limits::check()?;
+        syntax::parse(&self.lexer, &self.lr_parser, input)
+    }
+
+    /* Relexes only the tokens around the edit and keeps the subtrees it did not touch, returns the range of the new text whose tokens changed */
+    pub fn reparse(
+        &self,
+        tree: &SyntaxTree<T>,
+        edit: &TextEdit,
+    ) -> Result<(SyntaxTree<T>, Span), E> {
+        let _budget = self.limits.enter();
+        limits::check()?;
+        syntax::reparse(&self.lexer, &self.lr_parser, tree, edit)
+    }
+
+    /* Runs the script pausing in the session debugger before expressions are evaluated */
+    pub fn debug<D: Debugger<ENV> + 'static>(
+        &self,
+        env: &mut ENV,
+        input: &str,
+        session: &DebugSession<D>,
+    ) -> Result<R, E>
+    where
+        ENV: 'static,
+        T: 'static,
+        R: 'static,
+        E: 'static,
+    {
+        session.start(input);
+        let instrument = |node, rule, span| session.instrument(node, rule, span);
+        let result = self.execute(env, input, Some(&instrument), &self.limits);
+        if let Err(error) = &result {
+            self.notify(|o| o.error(&error.message()));
+        }
+        result
+    }
+
+    fn execute(
+        &self,
+        env: &mut ENV,
+        input: &str,
+        instrument: Option<&Instrument<'_, ENV, T, R, E>>,
+        limits: &ExecutionLimits,
+    ) -> Result<R, E> {
+        let _budget = limits.enter();
+        limits::check()?;
+        let tokens = self
+            .lexer
+            .parse_with(input, |token| self.notify(|o| o.token_lexed(token)))?;
+        /* the root is evaluated as an argument so that it gets a trace frame like any other */
+        let mut root = self.lr_parse(tokens, instrument)?;
+        let execution_result = match root.eval(env)? {
             ASTNode::Value(value) => value,
             _ => return Err(ParseError::IncorrectParseResult.into()),
         };
         Ok(execution_result)
     }
 
-    fn lr_parse(&self, tokens: Tokens<T>) -> super::error::Result<ASTNode<ENV, T, R, E>, E> {
+    fn lr_parse(
+        &self,
+        tokens: Tokens<T>,
+        instrument: Option<&Instrument<'_, ENV, T, R, E>>,
+    ) -> Result<ReducerArg<ENV, T, R, E>, E> {
         /* parse stack initial state 0 */
         let mut parse_stack = Vec::from([0]);
         let mut ast_stack = Vec::<ASTNode<ENV, T, R, E>>::new();
-        let mut iter = tokens.0.into_iter();
-        let mut token = match iter.next() {
-            Some(token) => token,
+        /* source span of every AST stack item */
+        let mut span_stack = Vec::<Span>::new();
+        let grammar_set = &self.lr_parser.grammar_set;
+        let max_depth = limits::max_parse_depth();
+        /* rule each AST stack item was reduced by, 0 for tokens */
+        let mut rule_stack = Vec::<usize>::new();
+        let store = Rc::new(ArgStore::new());
+        let mut iter = tokens.0.into_iter().zip(tokens.1);
+        let (mut token, mut span) = match iter.next() {
+            Some(next) => next,
             None => return Err(SyntaxError::SyntaxError.into()),
         };
-        while parse_stack.len() != 0 {
+        /* terminal symbol id of the lookahead, resolved once per token */
+        let terminal_id = |token: &Token<T>, span: Span| match grammar_set.terminal_id(token.r#type)
+        {
+            Some(symbol) => Ok(symbol),
+            None => Err(SyntaxError::UnexpectedToken(
+                format!("{:?}", token.r#type),
+                span,
+                vec![],
+            )),
+        };
+        let mut symbol = terminal_id(&token, span)?;
+        let coverage = self.coverage.as_deref();
+        if let Some(coverage) = coverage {
+            coverage.entered(0);
+        }
+        while !parse_stack.is_empty() {
             let state = match parse_stack.last() {
                 Some(&state) => state,
-                None => return Err(ParseError::Error("stack is empty when peek").into()),
+                None => return Err(ParseError::EmptyParseStack.into()),
             };
-            let action = self
-                .lr_parser
-                .get_action(state, &Symbol::Terminal(token.r#type))?;
-            #[cfg(feature = "debug_lrparser")]
-            println!(
-                "{:?} -> [{}] {}\n  AST stack{:?}",
-                token.r#type, state, action, ast_stack
-            );
-            match action {
-                TransitionAction::Shift(state) => {
-                    parse_stack.push(*state);
+            match self.lr_parser.action_at(state, symbol, span)? {
+                TransitionAction::Shift(next_state) => {
+                    self.notify(|o| o.shift(state, &token, next_state));
+                    if let Some(coverage) = coverage {
+                        coverage.entered(next_state);
+                    }
+                    parse_stack.push(next_state);
+                    if let Some(max) = max_depth.filter(|&max| parse_stack.len() > max) {
+                        return Err(LimitError::ParseStackTooDeep(max).into());
+                    }
                     /* push AST stack */
-                    #[cfg(feature = "debug_lrparser")]
-                    println!("  Shift [{:?}] -> {}", token, state);
                     ast_stack.push(ASTNode::Token(token));
-                    token = match iter.next() {
-                        Some(token) => token,
+                    span_stack.push(span);
+                    rule_stack.push(0);
+                    (token, span) = match iter.next() {
+                        Some(next) => next,
                         None => return Err(SyntaxError::SyntaxError.into()),
                     };
+                    symbol = terminal_id(&token, span)?;
                 }
                 TransitionAction::Reduce(rule_number) => {
                     let rule_idx = rule_number - 1;
-                    let grammar = match self.lr_parser.grammar_set.grammars.get(rule_idx) {
+                    let grammar = match grammar_set.grammars.get(rule_idx) {
                         Some(grammar) => grammar,
-                        None => return Err(ParseError::GrammarDoesNotExist(*rule_number).into()),
+                        None => return Err(ParseError::GrammarDoesNotExist(rule_number).into()),
                     };
                     /* pop AST stack to form AST params and the push a new AST expression */
                     if grammar.rvals.len() > parse_stack.len() {
-                        return Err(ParseError::Error("stack does not have enough items").into());
+                        return Err(ParseError::StackUnderflow(rule_number).into());
                     }
                     /* Pop rvals.len() items */
                     let remains = ast_stack.len() - grammar.rvals.len();
-                    let params = ast_stack.drain(remains..).collect();
-                    let args = ReducerArg::new(params);
-                    let ast_node = self.reducer[rule_idx](args);
-                    #[cfg(feature = "debug_lrparser")]
-                    println!("  Reduce [{}. {}] -> {}", rule_number, grammar, ast_node);
+                    let params = ast_stack.drain(remains..).map(Some).collect();
+                    /* an empty rule covers nothing right before the lookahead */
+                    let remains = span_stack.len() - grammar.rvals.len();
+                    let origins: Vec<_> = rule_stack
+                        .drain(remains..)
+                        .zip(span_stack.drain(remains..))
+                        .collect();
+                    let node_span = origins
+                        .iter()
+                        .map(|&(_, span)| span)
+                        .reduce(Span::to)
+                        .unwrap_or(Span {
+                            start: span.start,
+                            end: span.start,
+                        });
+                    self.notify(|o| o.reduce(state, grammar));
+                    if let Some(coverage) = coverage {
+                        coverage.reduced(rule_number);
+                    }
+                    let args = ReducerArg::new(
+                        params,
+                        grammar.labels.clone(),
+                        origins,
+                        rule_number,
+                        node_span,
+                        Rc::clone(&store),
+                    );
+                    let captured = Rc::downgrade(&args.args);
+                    let ast_node = self.reducer[rule_idx](args)?;
+                    self.notify(|o| o.reducer_evaluated(grammar, &ast_node));
+                    let ast_node = match instrument {
+                        Some(instrument) => instrument(ast_node, rule_number, node_span),
+                        None => ast_node,
+                    };
+                    if let ASTNode::ActionExpression(_, action) = &ast_node {
+                        store.captured(action, captured);
+                    }
                     ast_stack.push(ast_node);
+                    span_stack.push(node_span);
+                    rule_stack.push(rule_number);
                     let remains = parse_stack.len() - grammar.rvals.len();
                     parse_stack.truncate(remains);
                     /* Perform GOTO */
                     let state = match parse_stack.last() {
                         Some(&state) => state,
-                        None => return Err(ParseError::Error("stack is empty when goto").into()),
+                        None => return Err(ParseError::EmptyParseStack.into()),
                     };
-                    let goto_state = match self.lr_parser.get_action(state, &grammar.lval) {
-                        Ok(TransitionAction::Goto(state)) => *state,
-                        _ => return Err(ParseError::Error("goto action does not exist").into()),
+                    let goto_state = match self.lr_parser.get_action(state, grammar.lval_id) {
+                        Ok(TransitionAction::Goto(state)) => state,
+                        _ => return Err(ParseError::MissingGoto(state, rule_number).into()),
                     };
+                    self.notify(|o| o.goto(state, &grammar.lval, goto_state));
+                    if let Some(coverage) = coverage {
+                        coverage.entered(goto_state);
+                    }
                     parse_stack.push(goto_state);
                 }
                 TransitionAction::Accept => {
+                    self.notify(|o| o.accept(state));
                     /* AST stack should have exactly 1 item left, which is the returned expression */
-                    if let Some(expr) = ast_stack.pop() {
-                        if ast_stack.len() != 0 {
-                            return Err(ParseError::Error(
-                                "accepted but ast stack still has items left",
-                            )
-                            .into());
-                        }
-                        return Ok(expr);
-                    } else {
-                        return Err(ParseError::Error("accepted but ast stack is empty").into());
+                    if ast_stack.len() != 1 {
+                        return Err(ParseError::UnbalancedStack(ast_stack.len()).into());
                     }
+                    let origin = match (rule_stack.pop(), span_stack.pop()) {
+                        (Some(rule), Some(span)) => (rule, span),
+                        _ => (0, span),
+                    };
+                    let root = vec![ast_stack.pop()];
+                    let (labels, origins) = (vec![None], vec![origin]);
+                    let root = ReducerArg::new(root, labels, origins, 0, origin.1, store);
+                    return Ok(root);
                 }
-                TransitionAction::Goto(_) => {
-                    return Err(ParseError::Error("Unexpected goto action").into())
-                }
+                TransitionAction::Goto(_) => return Err(ParseError::UnexpectedGoto(state).into()),
             }
         }
         Err(SyntaxError::SyntaxError.into())
@@ -148,20 +438,114 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ScriptRunner<E
 }
 
 pub struct ReducerArg<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
-    args: VecDeque<ASTNode<ENV, T, R, E>>,
+    args: Rc<Arguments<ENV, T, R, E>>,
+    cursor: usize,
+}
+
+type Slots<ENV, T, R, E> = Vec<Option<ASTNode<ENV, T, R, E>>>;
+
+/* Arguments of a reduction, an ActionExpression that captures them shares them with the evaluator */
+struct Arguments<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    nodes: RefCell<Slots<ENV, T, R, E>>,
+    /* Set for the slots the evaluator filled, their nodes are results that are not evaluated again */
+    evaluated: Vec<Cell<bool>>,
+    labels: Vec<Option<&'static str>>,
+    /* Rule and source span each argument was reduced from, rule 0 for tokens */
+    origins: Vec<(usize, Span)>,
+    /* Rule and source span of the reduction, used for runtime error traces */
+    rule: usize,
+    span: Span,
+    store: Rc<ArgStore<ENV, T, R, E>>,
+}
+
+/* Arguments captured by the action of an ActionExpression, by the address of the action */
+type Captured<ENV, T, R, E> = HashMap<*const (), Weak<Arguments<ENV, T, R, E>>>;
+
+/* Shared by the ReducerArgs of one parse */
+struct ArgStore<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> {
+    /* Arguments that wait to be dropped */
+    draining: Cell<bool>,
+    dropped: RefCell<Vec<ASTNode<ENV, T, R, E>>>,
+    actions: RefCell<Captured<ENV, T, R, E>>,
+}
+
+impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ArgStore<ENV, T, R, E> {
+    fn new() -> Self {
+        Self {
+            draining: Cell::new(false),
+            dropped: RefCell::new(vec![]),
+            actions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /* Records the arguments of a reducer that returned the action, if the action kept them */
+    fn captured(&self, action: &Action<ENV, T, R, E>, args: Weak<Arguments<ENV, T, R, E>>) {
+        if args.strong_count() > 0 {
+            self.actions.borrow_mut().insert(address(action), args);
+        }
+    }
+}
+
+fn address<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError>(
+    action: &Action<ENV, T, R, E>,
+) -> *const () {
+    &**action as *const _ as *const ()
 }
 
 impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ReducerArg<ENV, T, R, E> {
-    fn new(args: VecDeque<ASTNode<ENV, T, R, E>>) -> Self {
-        Self { args }
+    fn new(
+        args: Vec<Option<ASTNode<ENV, T, R, E>>>,
+        labels: Vec<Option<&'static str>>,
+        origins: Vec<(usize, Span)>,
+        rule: usize,
+        span: Span,
+        store: Rc<ArgStore<ENV, T, R, E>>,
+    ) -> Self {
+        let evaluated = args.iter().map(|_| Cell::new(false)).collect();
+        let args = Arguments {
+            nodes: RefCell::new(args),
+            evaluated,
+            labels,
+            origins,
+            rule,
+            span,
+            store,
+        };
+        Self {
+            args: Rc::new(args),
+            cursor: 0,
+        }
+    }
+
+    /* Number of arguments that have not been consumed yet */
+    pub fn len(&self) -> usize {
+        let nodes = self.args.nodes.borrow();
+        nodes[self.cursor.min(nodes.len())..]
+            .iter()
+            .filter(|a| a.is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn peek(&self) -> Option<Ref<'_, ASTNode<ENV, T, R, E>>> {
+        let nodes = self.args.nodes.borrow();
+        Ref::filter_map(nodes, |nodes| {
+            nodes.get(self.cursor).and_then(Option::as_ref)
+        })
+        .ok()
     }
 
     pub fn eval(&mut self, env: &mut ENV) -> Result<ASTNode<ENV, T, R, E>, E> {
-        self.args.pop_front().unwrap().evaluate(env)
+        self.nth_eval(env, 0)
     }
 
     pub fn nth_eval(&mut self, env: &mut ENV, n: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
-        self.nth_node(n).evaluate(env)
+        let idx = self.cursor + n;
+        let node = self.nth_node(n)?;
+        self.evaluate_at(env, idx, node)
     }
 
     pub fn eval_skip(&mut self, env: &mut ENV, n: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
@@ -170,34 +554,184 @@ impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> ReducerArg<ENV
         node
     }
 
-    pub fn val(&mut self) -> ASTNode<ENV, T, R, E> {
-        self.args.pop_front().unwrap()
+    pub fn eval_named(
+        &mut self,
+        env: &mut ENV,
+        label: &'static str,
+    ) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let idx = self.label_position(label)?;
+        let node = self.take(idx)?;
+        self.evaluate_at(env, idx, node)
+    }
+
+    pub fn val(&mut self) -> Result<ASTNode<ENV, T, R, E>, E> {
+        self.nth_node(0)
     }
 
-    pub fn nth_val(&mut self, n: usize) -> ASTNode<ENV, T, R, E> {
+    pub fn nth_val(&mut self, n: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
         self.nth_node(n)
     }
 
-    pub fn val_skip(&mut self, n: usize) -> ASTNode<ENV, T, R, E> {
+    pub fn val_skip(&mut self, n: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
         let node = self.val();
         self.skip_n(n);
         node
     }
 
-    fn nth_node(&mut self, n: usize) -> ASTNode<ENV, T, R, E> {
-        for _ in 0..n {
-            self.skip()
+    /* Takes the argument bound to label:symbol in the grammar rule */
+    pub fn get(&mut self, label: &'static str) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let idx = self.label_position(label)?;
+        self.take(idx)
+    }
+
+    fn label_position(&self, label: &'static str) -> Result<usize, E> {
+        match self.args.labels.iter().position(|&l| l == Some(label)) {
+            Some(idx) => Ok(idx),
+            None => Err(ReducerError::UnknownLabel(label).into()),
+        }
+    }
+
+    /* Evaluates the next argument and expects it to produce a runtime value */
+    pub fn expect_value(&mut self, env: &mut ENV) -> Result<R, E> {
+        Ok(self.eval(env)?.into_value()?)
+    }
+
+    /* Takes the next argument and expects it to be an unevaluated token */
+    pub fn expect_token(&mut self) -> Result<Token<T>, E> {
+        Ok(self.val()?.into_token()?)
+    }
+
+    fn nth_node(&mut self, n: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let idx = self.cursor + n;
+        let node = self.take(idx)?;
+        self.cursor = idx + 1;
+        Ok(node)
+    }
+
+    /* An expression that fails gets the frame of the argument it was taken from */
+    fn evaluate_at(
+        &self,
+        env: &mut ENV,
+        idx: usize,
+        node: ASTNode<ENV, T, R, E>,
+    ) -> Result<ASTNode<ENV, T, R, E>, E> {
+        if self.args.evaluated[idx].get() {
+            return Ok(node);
+        }
+        let frame = match &node {
+            ASTNode::ActionExpression(name, _) => Some(self.slot_frame(idx, name)),
+            _ => None,
+        };
+        node.evaluate_in(env, Some(self))
+            .map_err(|error| match frame {
+                Some(frame) => error.traced(frame),
+                None => error,
+            })
+    }
+
+    fn take(&mut self, idx: usize) -> Result<ASTNode<ENV, T, R, E>, E> {
+        let node = self
+            .args
+            .nodes
+            .borrow_mut()
+            .get_mut(idx)
+            .and_then(Option::take);
+        match node {
+            Some(node) => Ok(node),
+            None => Err(ReducerError::MissingArgument(idx, self.args.evaluated.len()).into()),
         }
-        self.args.pop_front().unwrap()
     }
 
     pub fn skip(&mut self) {
-        self.args.pop_front();
+        self.skip_n(1)
     }
 
     pub fn skip_n(&mut self, n: usize) {
-        for _ in 0..n {
-            self.skip()
+        self.cursor = self.args.evaluated.len().min(self.cursor + n);
+    }
+
+    /* Builds a Reduction, the evaluator runs every expression argument before calling action so deep trees do not grow the Rust stack */
+    pub fn defer<F>(self, name: &'static str, action: F) -> ASTNode<ENV, T, R, E>
+    where
+        F: FnOnce(&mut ENV, ReducerArg<ENV, T, R, E>) -> Result<ASTNode<ENV, T, R, E>, E> + 'static,
+    {
+        ASTNode::Reduction(name, self, Box::new(action))
+    }
+
+    /* Takes the first unevaluated expression argument from slot onwards */
+    pub(crate) fn next_expression(&self, slot: usize) -> Option<(usize, ASTNode<ENV, T, R, E>)> {
+        let mut nodes = self.args.nodes.borrow_mut();
+        let idx = (slot..nodes.len()).find(|&idx| {
+            !self.args.evaluated[idx].get()
+                && matches!(
+                    nodes[idx],
+                    Some(ASTNode::ActionExpression(_, _) | ASTNode::Reduction(_, _, _))
+                )
+        })?;
+        Some((idx, nodes[idx].take()?))
+    }
+
+    pub(crate) fn fill(&self, slot: usize, node: ASTNode<ENV, T, R, E>) {
+        self.args.nodes.borrow_mut()[slot] = Some(node);
+        self.args.evaluated[slot].set(true);
+    }
+
+    /* The arguments the action of an ActionExpression of this parse captured, for the evaluator to evaluate first */
+    pub(crate) fn action_args(&self, action: &Action<ENV, T, R, E>) -> Option<Self> {
+        let mut actions = self.args.store.actions.borrow_mut();
+        let args = actions.remove(&address(action))?.upgrade()?;
+        Some(Self { args, cursor: 0 })
+    }
+
+    /* Reports a warning pointing at the source of this reduction */
+    pub fn warn(&self, message: impl Into<String>) {
+        diagnostic::warn(Diagnostic::warning(message).label(self.args.span, ""));
+    }
+
+    /* Frame of an expression argument, an expression passed through by a rule gets the rule and span of that rule */
+    pub(crate) fn slot_frame(&self, slot: usize, name: &'static str) -> TraceFrame {
+        let (rule, span) = self
+            .args
+            .origins
+            .get(slot)
+            .copied()
+            .unwrap_or((self.args.rule, self.args.span));
+        TraceFrame { name, rule, span }
+    }
+
+    pub(crate) fn trace_frame(&self, name: &'static str) -> TraceFrame {
+        TraceFrame {
+            name,
+            rule: self.args.rule,
+            span: self.args.span,
+        }
+    }
+}
+
+impl<ENV, T: ParserToken<T>, R: RuntimeValue<T>, E: RuntimeError> Drop
+    for ReducerArg<ENV, T, R, E>
+{
+    /* Queued while another ReducerArg of the parse is dropping, so a deep tree of any expressions is dropped in one loop,
+    arguments shared with the evaluator are dropped with the last ReducerArg */
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.args) > 1 {
+            return;
+        }
+        let queue = Rc::clone(&self.args.store);
+        queue
+            .dropped
+            .borrow_mut()
+            .extend(self.args.nodes.borrow_mut().drain(..).flatten());
+        if queue.draining.replace(true) {
+            return;
+        }
+        loop {
+            let node = queue.dropped.borrow_mut().pop();
+            match node {
+                Some(node) => drop(node),
+                None => break,
+            }
         }
+        queue.draining.set(false);
     }
 }
@@ -0,0 +1,259 @@
+/*
+Concrete syntax trees that are reparsed incrementally after an edit
+*/
+
+use std::{collections::HashMap, rc::Rc};
+
+use super::error::{LimitError, ParseError, Result, RuntimeError, SyntaxError};
+use super::lexer::Lexer;
+use super::limits;
+use super::lrparser::{LRParser, TransitionAction};
+use super::token::{ParserToken, Span, TextEdit, Token, TokenChange, Tokens};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxKind<T: ParserToken<T>> {
+    Token(Token<T>),
+    /* Number of the grammar rule that reduced the node */
+    Rule(usize),
+}
+
+/* Nodes only know their length and where their children start, so that trees whose text differs before a node can share it */
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyntaxNode<T: ParserToken<T>> {
+    pub kind: SyntaxKind<T>,
+    /* Bytes from the start of the first token to the end of the last */
+    pub len: usize,
+    /* Each child with its offset from the start of this node */
+    pub children: Vec<(usize, Rc<SyntaxNode<T>>)>,
+    /* Parse state the first token was read in */
+    state: usize,
+    tokens: usize,
+}
+
+pub struct SyntaxTree<T: ParserToken<T>> {
+    text: String,
+    tokens: Tokens<T>,
+    root: Rc<SyntaxNode<T>>,
+    start: usize,
+}
+
+impl<T: ParserToken<T>> SyntaxTree<T> {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn tokens(&self) -> &Tokens<T> {
+        &self.tokens
+    }
+
+    pub fn root(&self) -> &Rc<SyntaxNode<T>> {
+        &self.root
+    }
+
+    /* Every node with its span in the text, parents before their children */
+    pub fn nodes(&self) -> Vec<(Span, &Rc<SyntaxNode<T>>)> {
+        let mut nodes = vec![];
+        let mut stack = vec![(self.start, &self.root)];
+        while let Some((start, node)) = stack.pop() {
+            let end = start + node.len;
+            nodes.push((Span { start, end }, node));
+            let children = node.children.iter().rev();
+            stack.extend(children.map(|(offset, child)| (start + offset, child)));
+        }
+        nodes
+    }
+}
+
+/* Subtrees of the previous tree by the index of their first token */
+struct Reuse<T: ParserToken<T>> {
+    nodes: HashMap<usize, Vec<Rc<SyntaxNode<T>>>>,
+    change: TokenChange,
+    old_len: usize,
+}
+
+impl<T: ParserToken<T>> Reuse<T> {
+    fn new(tree: &SyntaxTree<T>, change: TokenChange) -> Reuse<T> {
+        let mut nodes: HashMap<usize, Vec<_>> = HashMap::new();
+        /* an ancestor is listed before the descendants that start with the same token */
+        let mut stack = vec![(0, &tree.root)];
+        while let Some((first, node)) = stack.pop() {
+            if matches!(node.kind, SyntaxKind::Rule(_)) && node.tokens > 0 {
+                nodes.entry(first).or_default().push(Rc::clone(node));
+            }
+            let mut next = first;
+            for (_, child) in &node.children {
+                stack.push((next, child));
+                next += child.tokens;
+            }
+        }
+        Reuse {
+            nodes,
+            change,
+            old_len: tree.tokens.0.len(),
+        }
+    }
+
+    /* The largest old subtree at the token that the parser would build again, which it does when it reads the same tokens, and the lookahead after them, from the same state */
+    fn find(&self, index: usize, state: usize) -> Option<&Rc<SyntaxNode<T>>> {
+        let TokenChange {
+            start,
+            old_end,
+            new_end,
+        } = self.change;
+        let first = match index {
+            _ if index < start => index,
+            _ if index >= new_end => index - new_end + old_end,
+            _ => return None,
+        };
+        self.nodes.get(&first)?.iter().find(|node| {
+            let next = first + node.tokens;
+            node.state == state && next < self.old_len && (next < start || first >= old_end)
+        })
+    }
+}
+
+pub(crate) fn parse<T: ParserToken<T>, E: RuntimeError>(
+    lexer: &Lexer<T>,
+    lr_parser: &LRParser<T>,
+    input: &str,
+) -> Result<SyntaxTree<T>, E> {
+    let tokens = lexer.parse(input)?;
+    build(lr_parser, input.to_string(), tokens, None)
+}
+
+pub(crate) fn reparse<T: ParserToken<T>, E: RuntimeError>(
+    lexer: &Lexer<T>,
+    lr_parser: &LRParser<T>,
+    tree: &SyntaxTree<T>,
+    edit: &TextEdit,
+) -> Result<(SyntaxTree<T>, Span), E> {
+    let text = match edit.apply(&tree.text) {
+        Some(text) => text,
+        None => return Err(ParseError::EditOutsideSource(edit.span).into()),
+    };
+    let (tokens, change) = lexer.relex(&tree.tokens, edit, &text)?;
+    /* the edit itself and every token lexed again */
+    let edited = Span {
+        start: edit.span.start,
+        end: edit.span.start + edit.text.len(),
+    };
+    let changed = tokens.1[change.start..change.new_end]
+        .iter()
+        .fold(edited, |changed, &span| changed.to(span));
+    let reuse = Reuse::new(tree, change);
+    Ok((build(lr_parser, text, tokens, Some(reuse))?, changed))
+}
+
+/* The LR loop of ScriptRunner without reducers, reused subtrees are taken with a single goto */
+fn build<T: ParserToken<T>, E: RuntimeError>(
+    lr_parser: &LRParser<T>,
+    text: String,
+    tokens: Tokens<T>,
+    reuse: Option<Reuse<T>>,
+) -> Result<SyntaxTree<T>, E> {
+    let grammar_set = &lr_parser.grammar_set;
+    let max_depth = limits::max_parse_depth();
+    let mut parse_stack = Vec::from([0]);
+    /* every node with the offset of its first token */
+    let mut node_stack = Vec::<(usize, Rc<SyntaxNode<T>>)>::new();
+    let mut index = 0;
+    loop {
+        let state = *parse_stack.last().expect("the start state");
+        if let Some(node) = reuse.as_ref().and_then(|reuse| reuse.find(index, state)) {
+            if let SyntaxKind::Rule(rule) = node.kind {
+                parse_stack.push(goto(lr_parser, state, rule)?);
+                if let Some(max) = max_depth.filter(|&max| parse_stack.len() > max) {
+                    return Err(LimitError::ParseStackTooDeep(max).into());
+                }
+                node_stack.push((tokens.1[index].start, Rc::clone(node)));
+                index += node.tokens;
+                continue;
+            }
+        }
+        let (token, span) = match (tokens.0.get(index), tokens.1.get(index)) {
+            (Some(token), Some(&span)) => (token, span),
+            _ => return Err(SyntaxError::SyntaxError.into()),
+        };
+        let symbol = match grammar_set.terminal_id(token.r#type) {
+            Some(symbol) => symbol,
+            None => {
+                let found = format!("{:?}", token.r#type);
+                return Err(SyntaxError::UnexpectedToken(found, span, vec![]).into());
+            }
+        };
+        match lr_parser.action_at(state, symbol, span)? {
+            TransitionAction::Shift(next_state) => {
+                parse_stack.push(next_state);
+                if let Some(max) = max_depth.filter(|&max| parse_stack.len() > max) {
+                    return Err(LimitError::ParseStackTooDeep(max).into());
+                }
+                let node = SyntaxNode {
+                    kind: SyntaxKind::Token(token.clone()),
+                    len: span.end - span.start,
+                    children: vec![],
+                    state,
+                    tokens: 1,
+                };
+                node_stack.push((span.start, Rc::new(node)));
+                index += 1;
+            }
+            TransitionAction::Reduce(rule_number) => {
+                let grammar = match grammar_set.grammars.get(rule_number - 1) {
+                    Some(grammar) => grammar,
+                    None => return Err(ParseError::GrammarDoesNotExist(rule_number).into()),
+                };
+                if grammar.rvals.len() >= parse_stack.len() {
+                    return Err(ParseError::StackUnderflow(rule_number).into());
+                }
+                let remains = parse_stack.len() - grammar.rvals.len();
+                let children = node_stack.split_off(node_stack.len() - grammar.rvals.len());
+                /* an empty rule covers nothing right before the lookahead */
+                let start = children.first().map_or(span.start, |&(start, _)| start);
+                let end = children
+                    .last()
+                    .map_or(start, |(start, node)| start + node.len);
+                parse_stack.truncate(remains);
+                let state = parse_stack[remains - 1];
+                parse_stack.push(goto(lr_parser, state, rule_number)?);
+                let node = SyntaxNode {
+                    kind: SyntaxKind::Rule(rule_number),
+                    len: end - start,
+                    tokens: children.iter().map(|(_, child)| child.tokens).sum(),
+                    children: children
+                        .into_iter()
+                        .map(|(offset, child)| (offset - start, child))
+                        .collect(),
+                    state,
+                };
+                node_stack.push((start, Rc::new(node)));
+            }
+            TransitionAction::Accept => {
+                return match (node_stack.pop(), node_stack.is_empty()) {
+                    (Some((start, root)), true) => Ok(SyntaxTree {
+                        text,
+                        tokens,
+                        root,
+                        start,
+                    }),
+                    _ => Err(ParseError::IncorrectParseResult.into()),
+                };
+            }
+            TransitionAction::Goto(_) => return Err(ParseError::UnexpectedGoto(state).into()),
+        }
+    }
+}
+
+fn goto<T: ParserToken<T>, E: RuntimeError>(
+    lr_parser: &LRParser<T>,
+    state: usize,
+    rule_number: usize,
+) -> Result<usize, E> {
+    let grammar = match lr_parser.grammar_set.grammars.get(rule_number - 1) {
+        Some(grammar) => grammar,
+        None => return Err(ParseError::GrammarDoesNotExist(rule_number).into()),
+    };
+    match lr_parser.get_action(state, grammar.lval_id) {
+        Ok(TransitionAction::Goto(state)) => Ok(state),
+        _ => Err(ParseError::MissingGoto(state, rule_number).into()),
+    }
+}
@@ -0,0 +1,157 @@
+/*
+Serializable parse table, the item-set construction can be skipped by loading a saved table
+*/
+
+use super::error::GrammarError;
+use super::lrparser::TransitionAction;
+
+const MAGIC: &[u8; 4] = b"RYPT";
+const VERSION: u8 = 2;
+
+/* Parse table keyed by symbol name, fingerprint identifies the grammar and the table construction it was built with */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseTable {
+    pub fingerprint: u64,
+    pub rules: Vec<String>,
+    pub table: Vec<Vec<(String, TransitionAction)>>,
+    /* Rule number each state reduces on terminals that have no action in its row */
+    pub default_reduce: Vec<Option<usize>>,
+}
+
+impl ParseTable {
+    /* Compact binary encoding: strings and counts are u32 length prefixed, little endian, a row ends with its default reduction or 0 */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(*MAGIC);
+        bytes.push(VERSION);
+        bytes.extend(self.fingerprint.to_le_bytes());
+        write_u32(&mut bytes, self.rules.len());
+        for rule in &self.rules {
+            write_str(&mut bytes, rule);
+        }
+        write_u32(&mut bytes, self.table.len());
+        for (state, row) in self.table.iter().enumerate() {
+            write_u32(&mut bytes, row.len());
+            for (symbol, action) in row {
+                write_str(&mut bytes, symbol);
+                let (tag, value) = match action {
+                    TransitionAction::Shift(s) => (0, *s),
+                    TransitionAction::Reduce(r) => (1, *r),
+                    TransitionAction::Goto(s) => (2, *s),
+                    TransitionAction::Accept => (3, 0),
+                };
+                bytes.push(tag);
+                write_u32(&mut bytes, value);
+            }
+            let default = self.default_reduce.get(state).copied().flatten();
+            write_u32(&mut bytes, default.unwrap_or(0));
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ParseTable, GrammarError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != MAGIC || reader.take(1)? != [VERSION] {
+            return Err(GrammarError::InvalidParseTable(
+                "unknown format".to_string(),
+            ));
+        }
+        let mut fingerprint = [0; 8];
+        fingerprint.copy_from_slice(reader.take(8)?);
+        let fingerprint = u64::from_le_bytes(fingerprint);
+        let rules = (0..reader.u32()?)
+            .map(|_| reader.string())
+            .collect::<Result<_, _>>()?;
+        let mut table = vec![];
+        let mut default_reduce = vec![];
+        for _ in 0..reader.u32()? {
+            let mut row = vec![];
+            for _ in 0..reader.u32()? {
+                let symbol = reader.string()?;
+                let tag = reader.take(1)?[0];
+                let value = reader.u32()?;
+                let action = match tag {
+                    0 => TransitionAction::Shift(value),
+                    1 => TransitionAction::Reduce(value),
+                    2 => TransitionAction::Goto(value),
+                    3 => TransitionAction::Accept,
+                    _ => {
+                        return Err(GrammarError::InvalidParseTable(
+                            "unknown action".to_string(),
+                        ))
+                    }
+                };
+                row.push((symbol, action));
+            }
+            table.push(row);
+            default_reduce.push(Some(reader.u32()?).filter(|&rule| rule != 0));
+        }
+        if reader.pos != bytes.len() {
+            return Err(GrammarError::InvalidParseTable(
+                "trailing bytes".to_string(),
+            ));
+        }
+        Ok(ParseTable {
+            fingerprint,
+            rules,
+            table,
+            default_reduce,
+        })
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend((value as u32).to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_u32(bytes, value.len());
+    bytes.extend(value.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GrammarError> {
+        match self.bytes.get(self.pos..self.pos + len) {
+            Some(slice) => {
+                self.pos += len;
+                Ok(slice)
+            }
+            None => Err(GrammarError::InvalidParseTable(
+                "unexpected end of data".to_string(),
+            )),
+        }
+    }
+
+    fn u32(&mut self) -> Result<usize, GrammarError> {
+        let mut value = [0; 4];
+        value.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(value) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, GrammarError> {
+        let len = self.u32()?;
+        match std::str::from_utf8(self.take(len)?) {
+            Ok(value) => Ok(value.to_string()),
+            Err(_) => Err(GrammarError::InvalidParseTable(
+                "invalid utf-8 string".to_string(),
+            )),
+        }
+    }
+}
+
+/* FNV-1a, stable across builds unlike std's DefaultHasher */
+pub(crate) fn fingerprint<'a>(parts: impl Iterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
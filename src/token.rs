@@ -8,6 +8,26 @@ pub struct LexerTokenMap<T> {
     pub integer: T,
     pub float: T,
     pub string: T,
+    /* Emitted for bad input by Lexer::parse_recovering, the grammar can refer to it as error */
+    pub error: Option<T>,
+}
+
+impl<T> LexerTokenMap<T> {
+    pub fn new(eof: T, identifier: T, integer: T, float: T, string: T) -> LexerTokenMap<T> {
+        LexerTokenMap {
+            eof,
+            identifier,
+            integer,
+            float,
+            string,
+            error: None,
+        }
+    }
+
+    pub fn error(mut self, error: T) -> LexerTokenMap<T> {
+        self.error = Some(error);
+        self
+    }
 }
 
 pub trait ParserToken<T: ParserToken<T>>:
@@ -16,7 +36,7 @@ pub trait ParserToken<T: ParserToken<T>>:
     fn entity(self, value: String) -> Token<T>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token<T: ParserToken<T>> {
     pub r#type: T,
     pub value: String,
@@ -42,7 +62,7 @@ impl<T: ParserToken<T>> SpecialTokenMap<T> {
         operator.iter().for_each(|def| {
             set.operator_map.insert(def.0, def.1);
         });
-        for (sign, _) in &set.operator_map {
+        for sign in set.operator_map.keys() {
             for ch in sign.chars() {
                 set.signs_chars_set.insert(ch);
             }
@@ -55,17 +75,21 @@ impl<T: ParserToken<T>> SpecialTokenMap<T> {
     }
 
     pub fn get_sign_type(&self, sign: &str) -> Option<T> {
-        match self.operator_map.get(sign) {
-            Some(token) => Some(*token),
-            None => None,
-        }
+        self.operator_map.get(sign).copied()
     }
 
     pub fn get_keyword_type(&self, sign: &str) -> Option<T> {
-        match self.keyword_map.get(sign) {
-            Some(token) => Some(*token),
-            None => None,
-        }
+        self.keyword_map.get(sign).copied()
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = (&'static str, T)> + '_ {
+        self.keyword_map.iter().map(|(&name, &token)| (name, token))
+    }
+
+    pub fn operators(&self) -> impl Iterator<Item = (&'static str, T)> + '_ {
+        self.operator_map
+            .iter()
+            .map(|(&name, &token)| (name, token))
     }
 
     pub fn is_keyword(&self, sign: &str) -> bool {
@@ -81,4 +105,60 @@ impl<T: ParserToken<T>> SpecialTokenMap<T> {
     }
 }
 
-pub struct Tokens<T: ParserToken<T>>(pub Vec<Token<T>>);
+/* Byte range of a token or a reduced expression in the source */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /* Smallest span that covers both */
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/* Lexed tokens and the span of each token */
+#[derive(Clone)]
+pub struct Tokens<T: ParserToken<T>>(pub Vec<Token<T>>, pub Vec<Span>);
+
+/* Replaces the source in span with text, spans are in bytes like token spans */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    /* None when the span is outside of the source or splits a character */
+    pub fn apply(&self, source: &str) -> Option<String> {
+        let Span { start, end } = self.span;
+        if start > end || !source.is_char_boundary(start) || !source.is_char_boundary(end) {
+            return None;
+        }
+        Some(format!(
+            "{}{}{}",
+            &source[..start],
+            self.text,
+            &source[end..]
+        ))
+    }
+
+    /* Difference in length between the new and the old source */
+    pub fn delta(&self) -> isize {
+        self.text.len() as isize - (self.span.end - self.span.start) as isize
+    }
+}
+
+/* Tokens before start and from old_end on were kept by Lexer::relex, the new tokens from new_end on are the old ones moved by the edit */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenChange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
@@ -0,0 +1,19 @@
+use ry_script::lrparser::CompiledGrammar;
+use ry_script_derive::grammar;
+
+static REDUCE_REDUCE: CompiledGrammar = grammar! {
+    #![allow_conflicts]
+    "B -> S EOF",
+    "S -> A",
+    "S -> C",
+    "A -> id",
+    "C -> id",
+};
+
+static SHIFT_REDUCE: CompiledGrammar = grammar! {
+    "B -> S EOF",
+    "S -> id",
+    "S -> id = int",
+};
+
+fn main() {}
@@ -0,0 +1,290 @@
+#[cfg(test)]
+mod lrparser_benchmark_tests {
+    use std::time::Instant;
+
+    use ry_script::{
+        ast::{never_reducer, value_reducer, RuntimeValue},
+        error::{RuntimeError, ScriptError},
+        grammar::{GrammarSet, TerminalSymbolDef},
+        lrparser::{LRParser, TableFormat, TransitionAction},
+        runner::{GrammarRule, ScriptRunner},
+        token::{LexerTokenMap, ParserToken, Token},
+    };
+
+    /* Numbered token types so that a grammar can have any number of keywords */
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    struct TokenType(u32);
+
+    const EOF: TokenType = TokenType(0);
+    const IDENTIFIER: TokenType = TokenType(1);
+    const INTEGER: TokenType = TokenType(2);
+    const FLOAT: TokenType = TokenType(3);
+    const STRING: TokenType = TokenType(4);
+
+    impl ParserToken<TokenType> for TokenType {
+        fn entity(self, value: String) -> Token<TokenType> {
+            Token {
+                r#type: self,
+                value,
+            }
+        }
+    }
+
+    struct NoError;
+
+    impl RuntimeError for NoError {}
+
+    impl std::fmt::Display for NoError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "no error")
+        }
+    }
+
+    #[derive(Debug)]
+    struct Value(String);
+
+    impl RuntimeValue<TokenType> for Value {}
+
+    impl From<Token<TokenType>> for Value {
+        fn from(token: Token<TokenType>) -> Self {
+            Value(token.value)
+        }
+    }
+
+    impl std::fmt::Display for Value {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    type Rule = GrammarRule<(), TokenType, Value, NoError>;
+
+    fn leak(text: String) -> &'static str {
+        Box::leak(text.into_boxed_str())
+    }
+
+    /* S -> Ci for every statement i, Ci -> ki Ei id and Ei -> int | ki Ei */
+    fn benchmark_grammar(statements: u32) -> (Vec<Rule>, Vec<TerminalSymbolDef<TokenType>>) {
+        let mut grammars = vec![GrammarRule("B -> S EOF", never_reducer)];
+        let mut keyword = vec![];
+        for i in 0..statements {
+            keyword.push(TerminalSymbolDef(leak(format!("k{}", i)), TokenType(5 + i)));
+            let rules = [
+                format!("S -> C{}", i),
+                format!("C{} -> k{} E{} id", i, i, i),
+                format!("E{} -> int", i),
+                format!("E{} -> k{} E{}", i, i, i),
+            ];
+            for rule in rules {
+                grammars.push(GrammarRule(leak(rule), value_reducer));
+            }
+        }
+        (grammars, keyword)
+    }
+
+    fn terminals(keyword: &[TerminalSymbolDef<TokenType>]) -> Vec<TerminalSymbolDef<TokenType>> {
+        let mut terminals = vec![
+            TerminalSymbolDef("id", IDENTIFIER),
+            TerminalSymbolDef("str", STRING),
+            TerminalSymbolDef("int", INTEGER),
+            TerminalSymbolDef("float", FLOAT),
+            TerminalSymbolDef("EOF", EOF),
+        ];
+        terminals.extend_from_slice(keyword);
+        terminals
+    }
+
+    /* E0 -> E0 o0 E1 | E1 ... E(n-1) -> E(n-1) o(n-1) En | En, En -> ( E0 ) | int */
+    fn ladder_grammar(levels: u32) -> (Vec<Rule>, Vec<TerminalSymbolDef<TokenType>>) {
+        let mut grammars = vec![GrammarRule("B -> E0 EOF", never_reducer)];
+        let mut keyword = vec![
+            TerminalSymbolDef("(", TokenType(5)),
+            TerminalSymbolDef(")", TokenType(6)),
+        ];
+        for i in 0..levels {
+            keyword.push(TerminalSymbolDef(leak(format!("o{}", i)), TokenType(7 + i)));
+            grammars.push(GrammarRule(
+                leak(format!("E{} -> E{} o{} E{}", i, i, i, i + 1)),
+                value_reducer,
+            ));
+            grammars.push(GrammarRule(
+                leak(format!("E{} -> E{}", i, i + 1)),
+                value_reducer,
+            ));
+        }
+        grammars.push(GrammarRule(
+            leak(format!("E{} -> ( E0 )", levels)),
+            value_reducer,
+        ));
+        grammars.push(GrammarRule(
+            leak(format!("E{} -> int", levels)),
+            value_reducer,
+        ));
+        (grammars, keyword)
+    }
+
+    #[test]
+    fn test_deep_grammar_table_construction() -> Result<(), ScriptError<NoError>> {
+        let levels = 300;
+        let (grammars, keyword) = ladder_grammar(levels);
+        let grammar_set = GrammarSet::new(&grammars, &terminals(&keyword), EOF)?;
+        let start = Instant::now();
+        let lr_parser = LRParser::lr0(grammar_set)?;
+        let elapsed = start.elapsed();
+        assert_eq!(lr_parser.state_count(), 6 + 3 * levels as usize);
+        assert!(
+            elapsed.as_secs() < 5,
+            "table construction took {:?}",
+            elapsed
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wide_grammar_table_construction() -> Result<(), ScriptError<NoError>> {
+        let statements = 500;
+        let (grammars, keyword) = benchmark_grammar(statements);
+        let grammar_set = GrammarSet::new(&grammars, &terminals(&keyword), EOF)?;
+        let start = Instant::now();
+        let lr_parser = LRParser::lr0(grammar_set)?;
+        let elapsed = start.elapsed();
+        /* start and S, then per statement the states after Ci, ki, ki Ei, ki Ei id, int, ki (in Ei), ki Ei (in Ei) */
+        assert_eq!(lr_parser.state_count(), 2 + 7 * statements as usize);
+        assert!(
+            elapsed.as_secs() < 5,
+            "table construction took {:?}",
+            elapsed
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_table_round_trip() -> Result<(), ScriptError<NoError>> {
+        let (grammars, keyword) = benchmark_grammar(50);
+        let terminals = terminals(&keyword);
+        let table = LRParser::lr0(GrammarSet::new(&grammars, &terminals, EOF)?)?.to_table();
+        let grammar_set = GrammarSet::new(&grammars, &terminals, EOF)?;
+        let reloaded = LRParser::from_table(grammar_set, &table)?;
+        assert_eq!(reloaded.state_count(), 2 + 7 * 50);
+        assert_eq!(reloaded.to_table(), table);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_export() -> Result<(), ScriptError<NoError>> {
+        let (grammars, keyword) = ladder_grammar(2);
+        let grammar_set = GrammarSet::new(&grammars, &terminals(&keyword), EOF)?;
+        let lr_parser = LRParser::lr0(grammar_set)?;
+        let dot = lr_parser.to_dot();
+        assert!(dot.starts_with("digraph LR {"));
+        assert!(dot.contains("s0 [label=\"0\\lB -> • E0 TokenType(0) \\l"));
+        assert!(dot.contains("-> accept [label=\"TokenType(0)\"]"));
+        /* E0 -> E1 • against E1 -> E1 • o1 E2 is a shift/reduce conflict */
+        assert!(!lr_parser.conflicts().is_empty());
+        for state in lr_parser.conflicts() {
+            let node = format!("    s{} [label=", state);
+            let line = dot.lines().find(|line| line.starts_with(&node)).unwrap();
+            assert!(line.contains("fillcolor"));
+        }
+        assert_eq!(
+            dot.matches("fillcolor").count(),
+            lr_parser.conflicts().len()
+        );
+        let edges = lr_parser
+            .to_table()
+            .table
+            .iter()
+            .flatten()
+            .filter(|(_, action)| {
+                matches!(
+                    action,
+                    TransitionAction::Shift(_) | TransitionAction::Goto(_)
+                )
+            })
+            .count();
+        assert_eq!(dot.matches(" -> s").count(), edges);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_dumps() -> Result<(), ScriptError<NoError>> {
+        let (grammars, keyword) = benchmark_grammar(1);
+        let build = || -> Result<_, ScriptError<NoError>> {
+            let grammar_set = GrammarSet::new(&grammars, &terminals(&keyword), EOF)?;
+            Ok(LRParser::lr0(grammar_set)?)
+        };
+        let lr_parser = build()?;
+        /* EOF, k0, id and int in order of first use, then the non-terminals in rule order */
+        let csv = [
+            "state,TokenType(0),TokenType(5),TokenType(1),TokenType(2),B,S,C0,E0",
+            "0,,shift 3,,,,goto 1,goto 2,",
+            "1,accept,,,,,,,",
+            "2,reduce 2,reduce 2,reduce 2,reduce 2,,,,",
+            "3,,shift 6,,shift 5,,,,goto 4",
+            "4,,,shift 7,,,,,",
+            "5,reduce 4,reduce 4,reduce 4,reduce 4,,,,",
+            "6,,shift 6,,shift 5,,,,goto 8",
+            "7,reduce 3,reduce 3,reduce 3,reduce 3,,,,",
+            "8,reduce 5,reduce 5,reduce 5,reduce 5,,,,",
+            "",
+        ];
+        assert_eq!(lr_parser.dump_table(TableFormat::Csv), csv.join("\n"));
+        let text = [
+            "| state | TokenType(0) | TokenType(5) | TokenType(1) | TokenType(2) | B | S      | C0     | E0     |",
+            "| 0     |              | shift 3      |              |              |   | goto 1 | goto 2 |        |",
+            "| 1     | accept       |              |              |              |   |        |        |        |",
+            "| 2     | reduce 2     | reduce 2     | reduce 2     | reduce 2     |   |        |        |        |",
+            "| 3     |              | shift 6      |              | shift 5      |   |        |        | goto 4 |",
+            "| 4     |              |              | shift 7      |              |   |        |        |        |",
+            "| 5     | reduce 4     | reduce 4     | reduce 4     | reduce 4     |   |        |        |        |",
+            "| 6     |              | shift 6      |              | shift 5      |   |        |        | goto 8 |",
+            "| 7     | reduce 3     | reduce 3     | reduce 3     | reduce 3     |   |        |        |        |",
+            "| 8     | reduce 5     | reduce 5     | reduce 5     | reduce 5     |   |        |        |        |",
+            "",
+        ];
+        assert_eq!(lr_parser.dump_table(TableFormat::Text), text.join("\n"));
+        assert!(lr_parser
+            .dump_item_sets()
+            .starts_with("State 0\n    B -> • S TokenType(0) \n    S -> • C0 \n"));
+        /* the state column and one for every symbol the rules use */
+        let symbols = &lr_parser.grammar_set.symbols;
+        let markdown = lr_parser.dump_table(TableFormat::Markdown);
+        assert_eq!(
+            markdown.lines().nth(1),
+            Some(&*format!("{}|", "| --- ".repeat(symbols.len() + 1)))
+        );
+        let html = lr_parser.dump_table(TableFormat::Html);
+        /* the state after k0 E0 shifts the identifier that ends the statement */
+        let identifier = lr_parser.grammar_set.terminal_id(IDENTIFIER).unwrap();
+        let state = (0..lr_parser.state_count())
+            .find(|&state| {
+                matches!(
+                    lr_parser.get_action(state, identifier),
+                    Ok(TransitionAction::Shift(_))
+                )
+            })
+            .unwrap();
+        let shift = lr_parser.get_action(state, identifier).unwrap();
+        let row = format!("<tr><td>{}</td>", state);
+        let row = html
+            .lines()
+            .find(|line| line.trim_start().starts_with(&row));
+        assert!(row.unwrap().contains(&format!("<td>{}</td>", shift)));
+        /* a rebuilt parser dumps byte for byte the same */
+        let rebuilt = build()?;
+        for format in [TableFormat::Text, TableFormat::Markdown, TableFormat::Html] {
+            assert_eq!(lr_parser.dump_table(format), rebuilt.dump_table(format));
+        }
+        assert_eq!(format!("{}", lr_parser), format!("{}", rebuilt));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wide_grammar_parse() -> Result<(), ScriptError<NoError>> {
+        let (grammars, keyword) = benchmark_grammar(200);
+        let token_map = LexerTokenMap::new(EOF, IDENTIFIER, INTEGER, FLOAT, STRING);
+        let runner = ScriptRunner::new(grammars, token_map, &[], &keyword)?;
+        assert_eq!(runner.run(&mut (), "k199 k199 k199 42 done")?.0, "k199");
+        Ok(())
+    }
+}
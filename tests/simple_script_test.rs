@@ -1,16 +1,37 @@
 #[cfg(test)]
 mod simple_script_tests {
-    use std::{collections::HashMap, hash::Hash};
+    use std::{
+        collections::{HashMap, HashSet},
+        hash::Hash,
+        rc::Rc,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
     use ry_script::{
         ast::{never_reducer, value_reducer, ASTNode, RuntimeValue},
-        error::{RuntimeError, ScriptError},
-        grammar::TerminalSymbolDef,
+        coverage::Coverage,
+        debugger::{Breakpoint, DebugCommand, DebugSession, Debugger, Frame},
+        diagnostic::{self, Diagnostic, Severity},
+        error::{
+            GrammarError, LexerError, LimitError, ParseError, ReducerError, RuntimeError,
+            ScriptError, SyntaxError,
+        },
+        generate::GenerateOptions,
+        grammar::{Grammar, TerminalSymbolDef},
+        lexer::Lexer,
+        limits::{CancellationToken, ExecutionLimits},
+        lrparser::{CompiledGrammar, TransitionAction},
+        observer::ParseObserver,
         runner::{GrammarRule, ReducerArg, ScriptRunner},
-        token::{LexerTokenMap, ParserToken, Token},
+        syntax::SyntaxTree,
+        table::ParseTable,
+        token::{LexerTokenMap, ParserToken, Span, SpecialTokenMap, TextEdit, Token},
     };
+    use ry_script_derive::grammar;
 
     /* Defines the types of token that will be used */
+    #[allow(clippy::upper_case_acronyms)]
     #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
     enum TokenType {
         Identifier,
@@ -25,6 +46,7 @@ mod simple_script_tests {
         Multiply,
         LeftParenthese,
         RightParenthese,
+        Error,
         EOF,
     }
 
@@ -40,8 +62,15 @@ mod simple_script_tests {
     /* Define runtime errors */
     type RuntimeResult<T> = std::result::Result<T, ScriptRuntimeError>;
 
-    impl RuntimeError for ScriptRuntimeError {}
+    impl RuntimeError for ScriptRuntimeError {
+        fn as_error(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(self)
+        }
+    }
+
+    impl std::error::Error for ScriptRuntimeError {}
 
+    #[derive(Debug)]
     enum ScriptRuntimeError {
         CannotCast(&'static str, String),
         NotImplemented(&'static str, String),
@@ -179,7 +208,10 @@ mod simple_script_tests {
             match lhs {
                 Value::Integer(lhs) => match rhs {
                     Value::Integer(rhs) => Ok(Value::Integer(lhs + rhs)),
-                    Value::Float(rhs) => Ok(Value::Float((*lhs as f64) + rhs)),
+                    Value::Float(rhs) => {
+                        diagnostic::warn(Diagnostic::warning("implicit conversion to float"));
+                        Ok(Value::Float((*lhs as f64) + rhs))
+                    }
                     _ => Err(ScriptRuntimeError::NotImplemented(
                         "Addition",
                         format!("{}", rhs),
@@ -211,87 +243,115 @@ mod simple_script_tests {
     }
 
     /* Grammar reducers that takes advantage of RuntimeValue */
+    type ReducerResult = ry_script::error::Result<
+        ASTNode<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+        ScriptRuntimeError,
+    >;
+
+    /* Reductions are evaluated by the explicit stack engine, operands are already evaluated when the closure runs */
     fn assignment_reducer(
-        mut args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
-    ) -> ASTNode<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError> {
-        ASTNode::ActionExpression(
-            "id = val",
-            Box::new(
-                move |env| match (args.eval_skip(env, 1)?, args.eval(env)?) {
-                    (ASTNode::Value(lhs), ASTNode::Value(rhs)) => {
-                        println!("eval {:?} = {:?}", lhs, rhs);
-                        Ok(ASTNode::Value(env.assign(lhs, rhs)?))
-                    }
-                    _ => panic!("Parse Error: Reducer expected value but non-value were given"),
-                },
-            ),
-        )
+        args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+    ) -> ReducerResult {
+        Ok(args.defer("id = val", |env, mut args| {
+            let lhs = args.get("lhs")?.into_value()?;
+            let rhs = args.get("rhs")?.into_value()?;
+            Ok(ASTNode::Value(env.assign(lhs, rhs)?))
+        }))
     }
 
     fn multiply_reducer(
-        mut args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
-    ) -> ASTNode<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError> {
-        ASTNode::ActionExpression(
-            "a * b",
-            Box::new(
-                move |env| match (args.eval_skip(env, 1)?, args.eval(env)?) {
-                    (ASTNode::Value(lhs), ASTNode::Value(rhs)) => {
-                        #[cfg(feature = "debug_ast")]
-                        println!("eval {:?} * {:?}", lhs, rhs);
-                        Ok(ASTNode::Value(env.mul(&lhs, &rhs)?))
-                    }
-                    _ => panic!("Parse Error: Reducer expected value but non-value were given"),
-                },
-            ),
-        )
+        args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+    ) -> ReducerResult {
+        Ok(args.defer("a * b", |env, mut args| {
+            let lhs = args.expect_value(env)?;
+            args.skip();
+            let rhs = args.expect_value(env)?;
+            Ok(ASTNode::Value(env.mul(&lhs, &rhs)?))
+        }))
     }
 
     fn add_reducer(
-        mut args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
-    ) -> ASTNode<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError> {
-        ASTNode::ActionExpression(
-            "a + b",
-            Box::new(
-                move |env| match (args.eval_skip(env, 1)?, args.eval(env)?) {
-                    (ASTNode::Value(lhs), ASTNode::Value(rhs)) => {
-                        #[cfg(feature = "debug_ast")]
-                        println!("eval {:?} + {:?}", lhs, rhs);
-                        Ok(ASTNode::Value(env.add(&lhs, &rhs)?))
-                    }
-                    _ => panic!("Parse Error: Reducer expected value but non-value were given"),
-                },
-            ),
-        )
+        args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+    ) -> ReducerResult {
+        Ok(args.defer("a + b", |env, mut args| {
+            let lhs = args.expect_value(env)?;
+            args.skip();
+            let rhs = args.expect_value(env)?;
+            Ok(ASTNode::Value(env.add(&lhs, &rhs)?))
+        }))
     }
 
     fn negative_number_reducer(
+        args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+    ) -> ReducerResult {
+        Ok(args.defer("-a", |env, mut args| {
+            args.skip();
+            let val = args.expect_value(env)?;
+            Ok(ASTNode::Value(env.negative(&val)?))
+        }))
+    }
+
+    fn unary_plus_reducer(
         mut args: ReducerArg<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
-    ) -> ASTNode<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError> {
-        ASTNode::ActionExpression(
-            "a + b",
-            Box::new(move |env| match args.nth_eval(env, 1)? {
-                ASTNode::Value(val) => {
-                    #[cfg(feature = "debug_ast")]
-                    println!("eval - {:?}", val);
-                    Ok(ASTNode::Value(env.negative(&val)?))
-                }
-                _ => panic!("Parse Error: Reducer expected value but non-value were given"),
-            }),
-        )
+    ) -> ReducerResult {
+        args.warn("unary plus has no effect");
+        args.nth_val(1)
     }
 
+    /* The same rules as init_simple_script_parser with the parse table built at compile time */
+    static SIMPLE_SCRIPT_GRAMMAR: CompiledGrammar = grammar! {
+        #![allow_conflicts]
+        "B -> S EOF",
+        "S -> A1",
+        "S -> lhs:id = rhs:A1",
+        "A1 -> A2",
+        "A1 -> A1 + A2",
+        "A2 -> A3",
+        "A2 -> A2 * A3",
+        "A3 -> Val",
+        "Val -> str",
+        "Val -> num",
+        "Val -> + num",
+        "Val -> - num",
+        "num -> id",
+        "num -> int",
+        "num -> float",
+        "num -> true",
+        "num -> false",
+        "Val -> ( A1 )",
+    };
+
     fn init_simple_script_parser() -> ry_script::error::Result<
         ScriptRunner<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
         ScriptRuntimeError,
+    > {
+        init_runner(TableSource::Build)
+    }
+
+    enum TableSource<'a> {
+        Build,
+        Compiled(&'a CompiledGrammar),
+        Saved(&'a ParseTable),
+    }
+
+    fn lexer_token_map() -> LexerTokenMap<TokenType> {
+        LexerTokenMap::new(
+            TokenType::EOF,
+            TokenType::Identifier,
+            TokenType::Integer,
+            TokenType::Float,
+            TokenType::String,
+        )
+    }
+
+    fn init_runner(
+        source: TableSource,
+    ) -> ry_script::error::Result<
+        ScriptRunner<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>,
+        ScriptRuntimeError,
     > {
         /* These construct the Lexer */
-        let token_map = LexerTokenMap {
-            eof: TokenType::EOF,
-            identifier: TokenType::Identifier,
-            integer: TokenType::Integer,
-            float: TokenType::Float,
-            string: TokenType::String,
-        };
+        let token_map = lexer_token_map();
         let operator = [
             /* Specify the possible operator that the lexer will recognize */
             TerminalSymbolDef("=", TokenType::Assignment),
@@ -311,7 +371,7 @@ mod simple_script_tests {
         let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
             GrammarRule("B -> S EOF", never_reducer),
             GrammarRule("S -> A1", value_reducer),
-            GrammarRule("S -> id = A1", assignment_reducer),
+            GrammarRule("S -> lhs:id = rhs:A1", assignment_reducer).labels(&["lhs", "rhs"]),
             GrammarRule("A1 -> A2", value_reducer),
             GrammarRule("A1 -> A1 + A2", add_reducer),
             GrammarRule("A2 -> A3", value_reducer),
@@ -319,7 +379,7 @@ mod simple_script_tests {
             GrammarRule("A3 -> Val", value_reducer),
             GrammarRule("Val -> str", value_reducer),
             GrammarRule("Val -> num", value_reducer),
-            GrammarRule("Val -> + num", |mut args| args.nth_val(1)),
+            GrammarRule("Val -> + num", unary_plus_reducer),
             GrammarRule("Val -> - num", negative_number_reducer),
             GrammarRule("num -> id", value_reducer),
             GrammarRule("num -> int", value_reducer),
@@ -328,53 +388,1339 @@ mod simple_script_tests {
             GrammarRule("num -> false", value_reducer),
             GrammarRule("Val -> ( A1 )", |mut args| args.nth_val(1)),
         ];
-        ScriptRunner::new(grammars, token_map, &operator, &keyword)
+        match source {
+            TableSource::Build => ScriptRunner::new(grammars, token_map, &operator, &keyword),
+            TableSource::Compiled(compiled) => ScriptRunner::with_compiled_grammar(
+                compiled, grammars, token_map, &operator, &keyword,
+            ),
+            TableSource::Saved(table) => {
+                ScriptRunner::with_parse_table(table, grammars, token_map, &operator, &keyword)
+            }
+        }
     }
 
     #[test]
     fn test_addition() -> Result<(), ScriptError<ScriptRuntimeError>> {
-        let mut runner = init_simple_script_parser()?;
+        let runner = init_simple_script_parser()?;
         let mut env = RuntimeEnvironment::new();
-        assert_eq!(runner.run(&mut env, &"1+1")?, Value::Integer(2));
-        assert_eq!(runner.run(&mut env, &"1+2.5")?, Value::Float(3.5));
-        assert_eq!(runner.run(&mut env, &"1.5+40")?, Value::Float(41.5));
-        assert_eq!(runner.run(&mut env, &"1.5+5.4")?, Value::Float(6.9));
+        assert_eq!(runner.run(&mut env, "1+1")?, Value::Integer(2));
+        assert_eq!(runner.run(&mut env, "1+2.5")?, Value::Float(3.5));
+        assert_eq!(runner.run(&mut env, "1.5+40")?, Value::Float(41.5));
+        assert_eq!(runner.run(&mut env, "1.5+5.4")?, Value::Float(6.9));
         Ok(())
     }
 
     #[test]
     fn test_multiplication_and_addition() -> Result<(), ScriptError<ScriptRuntimeError>> {
-        let mut runner = init_simple_script_parser()?;
+        let runner = init_simple_script_parser()?;
         let mut env = RuntimeEnvironment::new();
-        assert_eq!(runner.run(&mut env, &"1+2*3")?, Value::Integer(7));
-        assert_eq!(runner.run(&mut env, &"2*3+4")?, Value::Integer(10));
+        assert_eq!(runner.run(&mut env, "1+2*3")?, Value::Integer(7));
+        assert_eq!(runner.run(&mut env, "2*3+4")?, Value::Integer(10));
         Ok(())
     }
 
     #[test]
     fn test_parenthesis_priority() -> Result<(), ScriptError<ScriptRuntimeError>> {
-        let mut runner = init_simple_script_parser()?;
+        let runner = init_simple_script_parser()?;
         let mut env = RuntimeEnvironment::new();
-        assert_eq!(runner.run(&mut env, &"(1+2)*3")?, Value::Integer(9));
-        assert_eq!(runner.run(&mut env, &"2*(3+4)")?, Value::Integer(14));
+        assert_eq!(runner.run(&mut env, "(1+2)*3")?, Value::Integer(9));
+        assert_eq!(runner.run(&mut env, "2*(3+4)")?, Value::Integer(14));
         Ok(())
     }
 
     #[test]
     fn test_assignment() -> Result<(), ScriptError<ScriptRuntimeError>> {
-        let mut runner = init_simple_script_parser()?;
+        let runner = init_simple_script_parser()?;
         let mut env = RuntimeEnvironment::new();
         assert_eq!(
-            runner.run(&mut env, &"foo = 10")?,
+            runner.run(&mut env, "foo = 10")?,
             Value::Identifier("foo".to_string())
         );
         assert_eq!(
-            Value::Identifier("foo".to_string()).value(&mut env),
+            Value::Identifier("foo".to_string()).value(&env),
             &Value::Integer(10)
         );
-        let value = runner.run(&mut env, &"foo = foo * foo")?;
+        let value = runner.run(&mut env, "foo = foo * foo")?;
         assert_eq!(value.value(&env), &Value::Integer(100));
-        assert_eq!(runner.run(&mut env, &"-foo + -20")?, Value::Integer(-120));
+        assert_eq!(runner.run(&mut env, "-foo + -20")?, Value::Integer(-120));
+        Ok(())
+    }
+
+    /* Records every event as a line of text */
+    #[derive(Default)]
+    struct EventLog(Mutex<Vec<String>>);
+
+    impl ParseObserver<TokenType> for EventLog {
+        fn token_lexed(&self, token: &Token<TokenType>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("token {:?}", token.r#type));
+        }
+
+        fn shift(&self, state: usize, token: &Token<TokenType>, next_state: usize) {
+            let event = format!("[{}] shift {:?} -> {}", state, token.r#type, next_state);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn reduce(&self, state: usize, rule: &Grammar<TokenType>) {
+            let event = format!("[{}] reduce {}", state, rule);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn accept(&self, state: usize) {
+            self.0.lock().unwrap().push(format!("[{}] accept", state));
+        }
+
+        fn error(&self, error: &dyn std::fmt::Display) {
+            self.0.lock().unwrap().push(format!("error {}", error));
+        }
+    }
+
+    #[test]
+    fn test_parse_observer() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let mut runner = init_simple_script_parser()?;
+        /* states are looked up in the table, their numbering is up to the table construction */
+        let lr_parser = runner.lr_parser();
+        let symbol = |terminal| lr_parser.grammar_set.terminal_id(terminal).unwrap();
+        let state_with = |symbol, action| {
+            (0..lr_parser.state_count())
+                .find(|&state| lr_parser.get_action(state, symbol) == Ok(action))
+                .unwrap()
+        };
+        let shifted_int = match lr_parser.get_action(0, symbol(TokenType::Integer)) {
+            Ok(TransitionAction::Shift(state)) => state,
+            action => panic!("expected a shift, got {:?}", action),
+        };
+        let eof = symbol(TokenType::EOF);
+        let reduced_add = state_with(eof, TransitionAction::Reduce(5));
+        let accepted = state_with(eof, TransitionAction::Accept);
+        let events = Arc::new(EventLog::default());
+        runner.set_observer(Some(events.clone()));
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "1+2")?, Value::Integer(3));
+        {
+            let log = events.0.lock().unwrap();
+            assert_eq!(
+                log[..4],
+                ["token Integer", "token Plus", "token Integer", "token EOF"]
+            );
+            assert_eq!(log[4], format!("[0] shift Integer -> {}", shifted_int));
+            assert_eq!(log[5], format!("[{}] reduce num -> Integer ", shifted_int));
+            assert!(log.contains(&format!("[{}] reduce A1 -> A1 Plus A2 ", reduced_add)));
+            assert_eq!(log.iter().filter(|e| e.contains("shift")).count(), 3);
+            assert_eq!(log.last().unwrap(), &format!("[{}] accept", accepted));
+        }
+        events.0.lock().unwrap().clear();
+        assert!(runner.run(&mut env, "1 + * 2").is_err());
+        {
+            let log = events.0.lock().unwrap();
+            assert!(log.last().unwrap().starts_with("error "));
+            assert!(!log.iter().any(|e| e.ends_with("accept")));
+        }
+        /* tokens are reported as they are lexed, before the lexer fails */
+        events.0.lock().unwrap().clear();
+        assert!(runner.run(&mut env, "1 + \"a").is_err());
+        {
+            let log = events.0.lock().unwrap();
+            assert_eq!(log[..2], ["token Integer", "token Plus"]);
+            assert!(log[2].starts_with("error LexerError: Unterminated string"));
+            assert_eq!(log.len(), 3);
+        }
+        /* detached observers see nothing */
+        let count = events.0.lock().unwrap().len();
+        runner.set_observer(None);
+        runner.run(&mut env, "3")?;
+        assert_eq!(events.0.lock().unwrap().len(), count);
+        Ok(())
+    }
+
+    /* Records every pause with the value of foo at that point */
+    struct Recorder {
+        command: DebugCommand,
+        frames: Vec<(Frame, Option<Value>)>,
+    }
+
+    impl Debugger<RuntimeEnvironment> for Recorder {
+        fn pause(&mut self, frame: &Frame, env: &mut RuntimeEnvironment) -> DebugCommand {
+            self.frames
+                .push((*frame, env.variables.get("foo").cloned()));
+            self.command
+        }
+    }
+
+    fn debug_script(
+        session: DebugSession<Recorder>,
+    ) -> Result<Vec<(Frame, Option<Value>)>, ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        runner.run(&mut env, "foo = 1")?;
+        let value = runner.debug(&mut env, "foo = (foo +\n2) * -3", &session)?;
+        assert_eq!(value.value(&env), &Value::Integer(-9));
+        let frames = std::mem::take(&mut session.debugger_mut().frames);
+        Ok(frames)
+    }
+
+    fn recorder(command: DebugCommand) -> Recorder {
+        Recorder {
+            command,
+            frames: vec![],
+        }
+    }
+
+    #[test]
+    fn test_debugger_step() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let source = "foo = (foo +\n2) * -3";
+        let frames = debug_script(DebugSession::new(recorder(DebugCommand::Step)).step())?;
+        let frames: Vec<_> = frames
+            .iter()
+            .map(|(frame, foo)| {
+                let text = &source[frame.span.start..frame.span.end];
+                (frame.name, frame.rule, text, frame.line, foo.clone())
+            })
+            .collect();
+        let foo = Some(Value::Integer(1));
+        assert_eq!(
+            frames,
+            [
+                ("a + b", 5, "foo +\n2", 1, foo.clone()),
+                ("-a", 12, "-3", 2, foo.clone()),
+                ("a * b", 7, "(foo +\n2) * -3", 1, foo.clone()),
+                ("id = val", 3, source, 1, foo),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_debugger_breakpoints() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let names = |frames: Vec<(Frame, Option<Value>)>| -> Vec<&'static str> {
+            frames.iter().map(|(frame, _)| frame.name).collect()
+        };
+        let session = DebugSession::new(recorder(DebugCommand::Continue));
+        assert!(debug_script(session)?.is_empty());
+        let session = DebugSession::new(recorder(DebugCommand::Continue))
+            .breakpoint(Breakpoint::Rule(5))
+            .breakpoint(Breakpoint::Line(2));
+        assert_eq!(names(debug_script(session)?), ["a + b", "-a"]);
+        let session = DebugSession::new(recorder(DebugCommand::Continue))
+            .breakpoint(Breakpoint::Name("a * b"));
+        assert_eq!(names(debug_script(session)?), ["a * b"]);
+        /* stepping from a breakpoint pauses at every following expression */
+        let session =
+            DebugSession::new(recorder(DebugCommand::Step)).breakpoint(Breakpoint::Rule(7));
+        assert_eq!(names(debug_script(session)?), ["a * b", "id = val"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_execution_limits() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let mut runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        /* 1+2*3 evaluates two expressions */
+        let limits = ExecutionLimits::default().fuel(2);
+        assert_eq!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits)?,
+            Value::Integer(7)
+        );
+        let limits = ExecutionLimits::default().fuel(1);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits),
+            Err(ScriptError::OutOfFuel)
+        ));
+        let limits = ExecutionLimits::default().timeout(Duration::ZERO);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits),
+            Err(ScriptError::DeadlineExceeded)
+        ));
+        let limits = ExecutionLimits::default().timeout(Duration::from_secs(60));
+        assert!(runner.run_with_limits(&mut env, "1+2*3", &limits).is_ok());
+        /* a token cancels every run it was given to until then */
+        let token = CancellationToken::new();
+        runner.set_limits(ExecutionLimits::default().cancellation(token.clone()));
+        assert_eq!(runner.run(&mut env, "(1+2)*3")?, Value::Integer(9));
+        token.cancel();
+        assert!(matches!(
+            runner.run(&mut env, "(1+2)*3"),
+            Err(ScriptError::Cancelled)
+        ));
+        assert!(runner
+            .run_with_limits(&mut env, "(1+2)*3", &ExecutionLimits::default())
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_run_limits() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        /* the expression of S runs 1+2*3 twice with a runner of its own */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> int", |_| {
+                Ok(ASTNode::ActionExpression(
+                    "nested",
+                    Box::new(|env| {
+                        let inner = init_simple_script_parser()?;
+                        inner.run(env, "1+2*3")?;
+                        Ok(ASTNode::Value(inner.run(env, "1+2*3")?))
+                    }),
+                ))
+            }),
+        ];
+        let mut runner = ScriptRunner::new(grammars, lexer_token_map(), &[], &[])?;
+        let mut env = RuntimeEnvironment::new();
+        /* one for the outer expression and two for each nested run */
+        let limits = ExecutionLimits::default().fuel(5);
+        assert_eq!(
+            runner.run_with_limits(&mut env, "0", &limits)?,
+            Value::Integer(7)
+        );
+        let limits = ExecutionLimits::default().fuel(4);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "0", &limits),
+            Err(ScriptError::OutOfFuel)
+        ));
+        /* a nested run is cancelled with the run it is nested in */
+        let token = CancellationToken::new();
+        token.cancel();
+        runner.set_limits(ExecutionLimits::default().cancellation(token));
+        assert!(matches!(
+            runner.run(&mut env, "0"),
+            Err(ScriptError::Cancelled)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_limits() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        let limits = ExecutionLimits::default().max_source_len(4);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits),
+            Err(ScriptError::Limit(LimitError::SourceTooLong(4)))
+        ));
+        let limits = ExecutionLimits::default().max_tokens(3);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits),
+            Err(ScriptError::Limit(LimitError::TooManyTokens(3)))
+        ));
+        let limits = ExecutionLimits::default().max_parse_depth(8);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "((((((((1))))))))", &limits),
+            Err(ScriptError::Limit(LimitError::ParseStackTooDeep(8)))
+        ));
+        /* the product is evaluated inside the sum */
+        let limits = ExecutionLimits::default().max_eval_depth(1);
+        assert!(matches!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits),
+            Err(ScriptError::Limit(LimitError::EvaluationTooDeep(1)))
+        ));
+        /* syntax trees are built within the limits of the runner */
+        let mut runner = init_simple_script_parser()?;
+        runner.set_limits(ExecutionLimits::default().max_tokens(3));
+        assert!(matches!(
+            runner.parse_tree("1+2*3"),
+            Err(ScriptError::Limit(LimitError::TooManyTokens(3)))
+        ));
+        runner.set_limits(ExecutionLimits::default().max_parse_depth(8));
+        let tree = runner.parse_tree("(((1)))")?;
+        let edit = TextEdit {
+            span: Span { start: 3, end: 4 },
+            text: "((((((1))))))".to_string(),
+        };
+        assert!(matches!(
+            runner.reparse(&tree, &edit),
+            Err(ScriptError::Limit(LimitError::ParseStackTooDeep(8)))
+        ));
+        let limits = ExecutionLimits::default()
+            .max_source_len(5)
+            .max_tokens(6)
+            .max_parse_depth(8)
+            .max_eval_depth(2);
+        assert_eq!(
+            runner.run_with_limits(&mut env, "1+2*3", &limits)?,
+            Value::Integer(7)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep_expression() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let token_map = lexer_token_map();
+        let operator = [
+            TerminalSymbolDef("+", TokenType::Plus),
+            TerminalSymbolDef("-", TokenType::Minus),
+        ];
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> A", value_reducer),
+            GrammarRule("A -> A + V", add_reducer),
+            GrammarRule("A -> V", value_reducer),
+            GrammarRule("V -> - V", negative_number_reducer),
+            GrammarRule("V -> int", value_reducer),
+            GrammarRule("V -> str", value_reducer),
+        ];
+        let runner = ScriptRunner::new(grammars, token_map, &operator, &[])?;
+        let mut env = RuntimeEnvironment::new();
+        let terms = 100_000;
+        /* left recursive */
+        let source = vec!["1"; terms].join("+");
+        assert_eq!(runner.run(&mut env, &source)?, Value::Integer(terms as i64));
+        /* right recursive */
+        let source = format!("{}1", "- ".repeat(terms + 1));
+        assert_eq!(runner.run(&mut env, &source)?, Value::Integer(-1));
+        /* errors leave deep trees behind */
+        let source = format!("{}\"a\"", "- ".repeat(terms));
+        let error = runner.run(&mut env, &source).unwrap_err();
+        assert!(matches!(
+            error,
+            ScriptError::Runtime(ScriptRuntimeError::NotImplemented("Negative", _), _)
+        ));
+        assert_eq!(error.trace().len(), terms);
+        let source = format!("{}+", vec!["1"; terms].join("+"));
+        assert!(matches!(
+            runner.run(&mut env, &source),
+            Err(ScriptError::Syntax(SyntaxError::UnexpectedToken(_, _, _)))
+        ));
+        /* ActionExpressions that capture their arguments are evaluated and dropped without recursion too */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> A", value_reducer),
+            GrammarRule("A -> A + V", |mut args| {
+                Ok(ASTNode::ActionExpression(
+                    "a + b",
+                    Box::new(move |env| {
+                        let lhs = args.expect_value(env)?;
+                        args.skip();
+                        let rhs = args.expect_value(env)?;
+                        Ok(ASTNode::Value(env.add(&lhs, &rhs)?))
+                    }),
+                ))
+            }),
+            GrammarRule("A -> V", value_reducer),
+            GrammarRule("V -> - V", |mut args| {
+                args.skip();
+                Ok(ASTNode::ActionExpression(
+                    "-a",
+                    Box::new(move |env| {
+                        let val = args.expect_value(env)?;
+                        Ok(ASTNode::Value(env.negative(&val)?))
+                    }),
+                ))
+            }),
+            GrammarRule("V -> int", value_reducer),
+            GrammarRule("V -> str", value_reducer),
+        ];
+        let runner = ScriptRunner::new(grammars, lexer_token_map(), &operator, &[])?;
+        assert!(matches!(
+            runner.run(&mut env, &source),
+            Err(ScriptError::Syntax(SyntaxError::UnexpectedToken(_, _, _)))
+        ));
+        let source = vec!["1"; terms].join("+");
+        assert_eq!(runner.run(&mut env, &source)?, Value::Integer(terms as i64));
+        let source = format!("{}1", "- ".repeat(terms + 1));
+        assert_eq!(runner.run(&mut env, &source)?, Value::Integer(-1));
+        let source = format!("{}\"a\"", "- ".repeat(terms));
+        let error = runner.run(&mut env, &source).unwrap_err();
+        assert_eq!(error.trace().len(), terms);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostics() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        let mut render = |source: &str| runner.run(&mut env, source).err().unwrap().render(source);
+        assert_eq!(
+            render("foo = 1 $ 2"),
+            [
+                "error[E0101]: unexpected character `$`",
+                " --> 1:9",
+                "  |",
+                "1 | foo = 1 $ 2",
+                "  |         ^ not the start of any token",
+                "",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            render("(1 + 2"),
+            [
+                "error[E0401]: unexpected token EOF",
+                " --> 1:7",
+                "  |",
+                "1 | (1 + 2",
+                "  |       ^ unexpected here",
+                "  |",
+                "  = help: expected one of Plus, RightParenthese",
+                "",
+            ]
+            .join("\n")
+        );
+        /* runtime errors point at the expression that failed */
+        assert_eq!(
+            render("1 + x"),
+            [
+                "error[E0600]: RuntimeError: \"id(x)\" does not implemented Addition",
+                " --> 1:1",
+                "  |",
+                "1 | 1 + x",
+                "  | ^^^^^ in `a + b`",
+                "",
+            ]
+            .join("\n")
+        );
+        /* the coloured output differs only by its escape sequences */
+        let source = "foo = (1 +\n  * 2)";
+        let diagnostic = runner.run(&mut env, source).err().unwrap().diagnostic();
+        let ansi = diagnostic.render_ansi(source);
+        assert!(ansi.starts_with("\x1b[1;31merror[E0401]\x1b[0m"));
+        let mut stripped = String::new();
+        let mut rest = ansi.as_str();
+        while let Some(start) = rest.find('\x1b') {
+            stripped.push_str(&rest[..start]);
+            rest = &rest[start + rest[start..].find('m').unwrap() + 1..];
+        }
+        stripped.push_str(rest);
+        assert_eq!(stripped, diagnostic.render(source));
+        assert!(stripped.contains("2 |   * 2)\n  |   ^ unexpected here\n"));
+        /* secondary labels are underlined with dashes */
+        let diagnostic = Diagnostic::error("mismatched types")
+            .code("E9999")
+            .label(Span { start: 6, end: 9 }, "this is a string")
+            .secondary(Span { start: 0, end: 1 }, "this is a number");
+        assert_eq!(
+            diagnostic.render("1 + 2 \"a\""),
+            [
+                "error[E9999]: mismatched types",
+                " --> 1:7",
+                "  |",
+                "1 | 1 + 2 \"a\"",
+                "  |       ^^^ this is a string",
+                "1 | 1 + 2 \"a\"",
+                "  | - this is a number",
+                "",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_trait() -> Result<(), Box<dyn std::error::Error>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "1+2")?, Value::Integer(3));
+        /* runtime errors are reachable through source */
+        let error: Box<dyn std::error::Error> = runner.run(&mut env, "1 + x").unwrap_err().into();
+        assert!(matches!(
+            error.source().unwrap().downcast_ref(),
+            Some(ScriptRuntimeError::NotImplemented("Addition", _))
+        ));
+        let error = runner.run(&mut env, "foo = \"bar").unwrap_err();
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(
+            source.downcast_ref(),
+            Some(&LexerError::UnterminatedString(Span { start: 6, end: 10 }))
+        );
+        /* grammar errors name the rule and the offending symbol */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> int + nope", value_reducer),
+        ];
+        let token_map = lexer_token_map();
+        let operator = [TerminalSymbolDef("+", TokenType::Plus)];
+        let error = ScriptRunner::new(grammars, token_map, &operator, &[]).err();
+        assert!(matches!(
+            error,
+            Some(ScriptError::Grammar(GrammarError::InvalidSymbol(2, ref symbol))) if symbol == "nope"
+        ));
+        /* the message of the source is not repeated by the outer error */
+        let error = error.unwrap();
+        assert_eq!(error.to_string(), "GrammarError");
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "Invalid symbol nope in rule 2");
+        assert_eq!(
+            error.message(),
+            "GrammarError: Invalid symbol nope in rule 2"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_terminal_rule_lval() {
+        /* id is a terminal, a rule with id as lval is an error instead of renumbering it */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> id", value_reducer),
+            GrammarRule("id -> int", value_reducer),
+        ];
+        let error = ScriptRunner::new(grammars, lexer_token_map(), &[], &[]).err();
+        assert!(matches!(
+            error,
+            Some(ScriptError::Grammar(GrammarError::InvalidSymbol(2, ref symbol))) if symbol == "id"
+        ));
+    }
+
+    #[test]
+    fn test_runtime_trace() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        let source = "foo = 2 *\n  (1 + \"a\")";
+        let error = runner.run(&mut env, source).unwrap_err();
+        assert!(matches!(
+            error,
+            ScriptError::Runtime(ScriptRuntimeError::NotImplemented("Addition", _), _)
+        ));
+        /* the parenthesis rule passes the addition through without a frame of its own */
+        let frames: Vec<_> = error
+            .trace()
+            .iter()
+            .map(|frame| (frame.name, frame.rule, frame.span.start, frame.span.end))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("a + b", 5, 13, 20),
+                ("a * b", 7, 6, 21),
+                ("id = val", 3, 0, 21)
+            ]
+        );
+        assert_eq!(
+            error.render_trace(source),
+            "in `a + b` at 2:4, in `a * b` at 1:7, in `id = val` at 1:1"
+        );
+        let rendered = error.render(source);
+        assert!(rendered.starts_with("error[E0600]: RuntimeError: "));
+        assert!(rendered.contains("^^^^^^^ in `a + b`"));
+        assert!(rendered.contains("- in `id = val`"));
+        /* errors outside of any expression have no trace */
+        assert!(runner.run(&mut env, "1 +").unwrap_err().trace().is_empty());
+        /* an ActionExpression is traced at the rule and span it is taken from, the parenthesis here */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> V", value_reducer),
+            GrammarRule("V -> - V", |mut args| {
+                args.skip();
+                Ok(ASTNode::ActionExpression(
+                    "-a",
+                    Box::new(move |env| {
+                        let val = args.expect_value(env)?;
+                        Ok(ASTNode::Value(env.negative(&val)?))
+                    }),
+                ))
+            }),
+            GrammarRule("V -> ( V )", |mut args| args.nth_val(1)),
+            GrammarRule("V -> str", value_reducer),
+        ];
+        let operator = [
+            TerminalSymbolDef("-", TokenType::Minus),
+            TerminalSymbolDef("(", TokenType::LeftParenthese),
+            TerminalSymbolDef(")", TokenType::RightParenthese),
+        ];
+        let runner = ScriptRunner::new(grammars, lexer_token_map(), &operator, &[])?;
+        let error = runner.run(&mut env, "-(-\"a\")").unwrap_err();
+        let frames: Vec<_> = error
+            .trace()
+            .iter()
+            .map(|frame| (frame.name, frame.rule, frame.span.start, frame.span.end))
+            .collect();
+        assert_eq!(frames, [("-a", 4, 1, 7), ("-a", 2, 0, 7)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_warnings() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        let source = "x = +1 + 2.5";
+        let (value, warnings) = runner.run_with_warnings(&mut env, source)?;
+        assert_eq!(value, Value::Identifier("x".to_string()));
+        assert_eq!(env.variables["x"], Value::Float(3.5));
+        /* reducers warn while parsing, expressions once they are evaluated */
+        let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["unary plus has no effect", "implicit conversion to float"]
+        );
+        assert!(warnings.iter().all(|w| w.severity == Severity::Warning));
+        assert_eq!(warnings[0].labels[0].span, Span { start: 4, end: 6 });
+        assert!(warnings[0]
+            .render(source)
+            .starts_with("warning: unary plus has no effect\n --> 1:5\n"));
+        assert!(warnings[1].labels.is_empty());
+        /* a plain run drops them */
+        assert_eq!(runner.run(&mut env, "+1")?, Value::Integer(1));
+        let (_, warnings) = runner.run_with_warnings(&mut env, "1 + 2")?;
+        assert!(warnings.is_empty());
+        /* a nested run collects its own warnings and keeps the ones of the run it is nested in */
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> int", |args| {
+                args.warn("before");
+                Ok(ASTNode::ActionExpression(
+                    "nested",
+                    Box::new(|env| {
+                        let inner = init_simple_script_parser()?;
+                        let (value, warnings) = inner.run_with_warnings(env, "x = +1 + 2.5")?;
+                        assert_eq!(warnings.len(), 2);
+                        assert!(inner.run_with_warnings(env, "+1 +").is_err());
+                        diagnostic::warn(Diagnostic::warning("after"));
+                        Ok(ASTNode::Value(value))
+                    }),
+                ))
+            }),
+        ];
+        let runner = ScriptRunner::new(grammars, lexer_token_map(), &[], &[])?;
+        let (_, warnings) = runner.run_with_warnings(&mut env, "0")?;
+        let messages: Vec<_> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(messages, ["before", "after"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_reparse() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let node_at = |tree: &SyntaxTree<TokenType>, start, end| {
+            let nodes = tree.nodes();
+            let (_, node) = nodes
+                .iter()
+                .find(|(span, _)| *span == Span { start, end })
+                .expect("a node with the span");
+            Rc::clone(node)
+        };
+        let tree = runner.parse_tree("x = (1 + 2) * (3 + 4)")?;
+        let edit = |start, end, text: &str| TextEdit {
+            span: Span { start, end },
+            text: text.to_string(),
+        };
+        /* the left operand ends before the edit */
+        let (edited, changed) = runner.reparse(&tree, &edit(19, 20, "40"))?;
+        assert_eq!(edited.text(), "x = (1 + 2) * (3 + 40)");
+        assert_eq!(changed, Span { start: 19, end: 21 });
+        let fresh = runner.parse_tree(edited.text())?;
+        assert_eq!(edited.root(), fresh.root());
+        assert_eq!(edited.tokens().0, fresh.tokens().0);
+        assert_eq!(edited.tokens().1, fresh.tokens().1);
+        assert!(Rc::ptr_eq(&node_at(&tree, 4, 11), &node_at(&edited, 4, 11)));
+        /* the right operand starts after the edit and moves with it */
+        let (moved, changed) = runner.reparse(&edited, &edit(5, 6, "100"))?;
+        assert_eq!(moved.text(), "x = (100 + 2) * (3 + 40)");
+        assert_eq!(changed, Span { start: 4, end: 8 });
+        assert_eq!(moved.root(), runner.parse_tree(moved.text())?.root());
+        assert!(Rc::ptr_eq(
+            &node_at(&edited, 14, 22),
+            &node_at(&moved, 16, 24)
+        ));
+        assert!(!Rc::ptr_eq(
+            &node_at(&edited, 4, 11),
+            &node_at(&moved, 4, 13)
+        ));
+        /* an edit can join tokens, or break the tree */
+        let (joined, changed) = runner.reparse(&moved, &edit(1, 1, "1"))?;
+        assert_eq!(joined.text(), "x1 = (100 + 2) * (3 + 40)");
+        assert_eq!(changed, Span { start: 0, end: 2 });
+        assert_eq!(joined.tokens().0[0].value, "x1");
+        assert_eq!(joined.root(), runner.parse_tree(joined.text())?.root());
+        let error = runner.reparse(&moved, &edit(1, 1, " *")).err();
+        assert!(matches!(
+            error,
+            Some(ScriptError::Syntax(SyntaxError::UnexpectedToken(_, _, _)))
+        ));
+        let error = runner.reparse(&moved, &edit(90, 91, "1")).err();
+        assert!(matches!(
+            error,
+            Some(ScriptError::Parse(ParseError::EditOutsideSource(Span {
+                start: 90,
+                end: 91
+            })))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reparse_relex() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let edit = |start, end, text: &str| TextEdit {
+            span: Span { start, end },
+            text: text.to_string(),
+        };
+        let same_as_fresh = |tree: &SyntaxTree<TokenType>| -> Result<(), ScriptError<_>> {
+            let fresh = runner.parse_tree(tree.text())?;
+            assert_eq!(tree.root(), fresh.root());
+            assert_eq!(tree.tokens().0, fresh.tokens().0);
+            assert_eq!(tree.tokens().1, fresh.tokens().1);
+            Ok(())
+        };
+        /* a comment opened by the edit swallows old tokens up to the end of the line */
+        let tree = runner.parse_tree("x = 1 + 2 *\n 3 + 4")?;
+        let (edited, _) = runner.reparse(&tree, &edit(8, 8, "#"))?;
+        assert_eq!(edited.text(), "x = 1 + #2 *\n 3 + 4");
+        assert_eq!(edited.tokens().0.len(), tree.tokens().0.len() - 2);
+        same_as_fresh(&edited)?;
+        /* a string opened by the edit runs into the quote of an old one */
+        let tree = runner.parse_tree("x = 1 + \"ab\"")?;
+        let (edited, _) = runner.reparse(&tree, &edit(4, 9, "\"1 + "))?;
+        assert_eq!(edited.text(), "x = \"1 + ab\"");
+        assert_eq!(edited.tokens().0[2].value, "1 + ab");
+        same_as_fresh(&edited)?;
+        let tree = runner.parse_tree("x = 1 + 2")?;
+        assert!(matches!(
+            runner.reparse(&tree, &edit(4, 4, "\"")),
+            Err(ScriptError::Lexer(LexerError::UnterminatedString(Span {
+                start: 4,
+                end: 10
+            })))
+        ));
+        /* spans of multi-byte characters are in bytes, an edit cannot split one */
+        let tree = runner.parse_tree("x = \"héllo\" + 1")?;
+        let (edited, _) = runner.reparse(&tree, &edit(6, 8, "üü"))?;
+        assert_eq!(edited.text(), "x = \"hüüllo\" + 1");
+        assert_eq!(edited.tokens().1[4], Span { start: 17, end: 18 });
+        same_as_fresh(&edited)?;
+        assert!(matches!(
+            runner.reparse(&tree, &edit(7, 7, "e")),
+            Err(ScriptError::Parse(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let grammar_set = &runner.lr_parser().grammar_set;
+        let options = GenerateOptions::new()
+            .seed(7)
+            .max_depth(12)
+            .count(500)
+            .values(TokenType::Identifier, &["x", "y"])
+            .values(TokenType::Integer, &["0", "2", "41"])
+            .values(TokenType::Float, &["1.5"])
+            .values(TokenType::String, &["\"s\""]);
+        let sentences = grammar_set.generate(&options);
+        assert_eq!(sentences.len(), 500);
+        assert_eq!(grammar_set.generate(&options), sentences);
+        assert_ne!(grammar_set.generate(&options.clone().seed(8)), sentences);
+        for sentence in &sentences {
+            let tokens = runner.lexer().parse(&sentence.source)?;
+            let types = |tokens: &[Token<TokenType>]| -> Vec<_> {
+                tokens.iter().map(|token| token.r#type).collect()
+            };
+            assert_eq!(types(&sentence.tokens), types(&tokens.0));
+            /* well formed scripts only fail at runtime */
+            let result = runner.run(&mut RuntimeEnvironment::new(), &sentence.source);
+            assert!(
+                matches!(result, Ok(_) | Err(ScriptError::Runtime(_, _))),
+                "{}: {:?}",
+                sentence.source,
+                result.err()
+            );
+        }
+
+        let exhaustive = GenerateOptions::new()
+            .max_depth(7)
+            .count(1_000)
+            .exhaustive()
+            .values(TokenType::Identifier, &["x"])
+            .values(TokenType::Integer, &["1"])
+            .values(TokenType::Float, &["1.5"])
+            .values(TokenType::String, &["\"s\""]);
+        let sentences = grammar_set.generate(&exhaustive);
+        let sources: HashSet<_> = sentences.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources.len(), sentences.len());
+        assert_eq!(sentences.len(), 128);
+        assert!(sources.contains("x = 1") && sources.contains("false"));
+        for sentence in &sentences {
+            runner.parse_tree(&sentence.source)?;
+        }
+        let deeper = grammar_set.generate(&exhaustive.clone().max_depth(9).count(50));
+        assert_eq!(deeper.len(), 50);
+        for sentence in &deeper {
+            runner.parse_tree(&sentence.source)?;
+        }
+        assert!(grammar_set
+            .generate(&GenerateOptions::new().max_depth(5))
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_coverage() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let mut runner = init_simple_script_parser()?;
+        let coverage = Arc::new(Coverage::new(runner.lr_parser()));
+        runner.set_coverage(Some(Arc::clone(&coverage)));
+        let mut env = RuntimeEnvironment::new();
+        runner.run(&mut env, "x = 1 + 2")?;
+        runner.run(&mut env, "x + 3")?;
+        /* A1 -> A1 + A2 */
+        assert_eq!(coverage.reduces(5), 2);
+        assert_eq!(coverage.visits(0), 2);
+        let report = coverage.report(runner.lr_parser());
+        assert!(!report.is_complete());
+        let unreduced: Vec<_> = report
+            .unreduced
+            .iter()
+            .map(|(_, rule)| rule.as_str())
+            .collect();
+        assert!(unreduced.contains(&"Val -> LeftParenthese A1 RightParenthese"));
+        assert!(unreduced.contains(&"Val -> Minus num"));
+        assert!(!unreduced.contains(&"S -> lhs:id = rhs:A1"));
+        assert!(!report.unvisited.is_empty());
+        let text = report.to_string();
+        assert!(text.contains("never reduced:\n"));
+        assert!(text.contains("\n  18. Val -> LeftParenthese A1 RightParenthese\n"));
+        assert!(text.contains("never entered: "));
+
+        let options = GenerateOptions::new()
+            .max_depth(10)
+            .count(300)
+            .values(TokenType::Identifier, &["x"])
+            .values(TokenType::Integer, &["1"])
+            .values(TokenType::Float, &["1.5"])
+            .values(TokenType::String, &["\"s\""]);
+        for sentence in runner.lr_parser().grammar_set.generate(&options) {
+            let _ = runner.run(&mut RuntimeEnvironment::new(), &sentence.source);
+        }
+        let report = coverage.report(runner.lr_parser());
+        assert!(report.is_complete(), "{}", report);
+        assert!(report
+            .to_string()
+            .starts_with("rules reduced: 17/17 (100.0%)\n"));
+
+        coverage.reset();
+        runner.set_coverage(None);
+        runner.run(&mut env, "1")?;
+        assert_eq!(coverage.visits(0), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "lsp")]
+    #[test]
+    fn test_language_server() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        use ry_script::lsp::LanguageServer;
+        use serde_json::{json, Value};
+
+        let mut server =
+            LanguageServer::new(init_simple_script_parser()?).environment(RuntimeEnvironment::new);
+        let uri = "file:///main.ry";
+        let document = |text: &str| json!({ "uri": uri, "text": text });
+        let at = |character: usize| json!({ "textDocument": { "uri": uri }, "position": { "line": 0, "character": character } });
+        let messages = [
+            json!({ "id": 1, "method": "initialize", "params": {} }),
+            json!({ "method": "textDocument/didOpen", "params": { "textDocument": document("x = 1 + \"a\"") } }),
+            json!({ "id": 2, "method": "textDocument/semanticTokens/full", "params": { "textDocument": { "uri": uri } } }),
+            json!({ "method": "textDocument/didChange", "params": { "textDocument": { "uri": uri }, "contentChanges": [{ "text": "x = tr" }] } }),
+            json!({ "id": 3, "method": "textDocument/completion", "params": at(6) }),
+            json!({ "id": 4, "method": "textDocument/completion", "params": at(4) }),
+            json!({ "method": "textDocument/didChange", "params": { "textDocument": { "uri": uri }, "contentChanges": [{ "text": "x = (1" }] } }),
+            json!({ "id": 5, "method": "shutdown" }),
+            json!({ "method": "exit" }),
+            json!({ "id": 6, "method": "shutdown" }),
+        ];
+        let input: String = messages
+            .iter()
+            .map(|message| {
+                let body = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect();
+        let mut output = vec![];
+        server.serve(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let replies: Vec<Value> = output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|message| serde_json::from_str(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+        /* nothing is answered after exit */
+        assert_eq!(replies.len(), 8);
+        let legend = &replies[0]["result"]["capabilities"]["semanticTokensProvider"]["legend"];
+        assert_eq!(
+            legend["tokenTypes"],
+            json!(["keyword", "operator", "variable", "number", "string"])
+        );
+        /* running the document reports the runtime error at the failing expression */
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        let diagnostic = &replies[1]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["code"], "E0600");
+        assert_eq!(diagnostic["severity"], 1);
+        assert_eq!(
+            diagnostic["range"],
+            json!({ "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 11 } })
+        );
+        assert_eq!(
+            replies[2]["result"]["data"],
+            json!([0, 0, 1, 2, 0, 0, 2, 1, 1, 0, 0, 2, 1, 3, 0, 0, 2, 1, 1, 0, 0, 2, 3, 4, 0])
+        );
+        assert_eq!(replies[3]["params"]["diagnostics"], json!([]));
+        /* keywords that complete the word, then everything the parser accepts */
+        assert_eq!(
+            replies[4]["result"],
+            json!([{ "label": "true", "kind": 14 }])
+        );
+        let labels: Vec<_> = replies[5]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["(", "+", "-", "false", "true"]);
+        let diagnostic = &replies[6]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["code"], "E0401");
+        assert_eq!(
+            diagnostic["range"]["start"],
+            json!({ "line": 0, "character": 6 })
+        );
+        assert_eq!(
+            replies[7],
+            json!({ "jsonrpc": "2.0", "id": 5, "result": null })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recovering_lexer() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let token_map = lexer_token_map().error(TokenType::Error);
+        let operator = [
+            TerminalSymbolDef("+", TokenType::Plus),
+            TerminalSymbolDef("==", TokenType::Assignment),
+        ];
+        let lexer = Lexer::new(token_map, SpecialTokenMap::new(&operator, &[]));
+        let source = "1 $ 2 = 3 @\n\"open";
+        let (tokens, errors) = lexer.parse_recovering(source)?;
+        let span = |start, end| Span { start, end };
+        assert_eq!(
+            errors,
+            [
+                LexerError::UnexpectedToken('$', span(2, 3)),
+                LexerError::UnexpectedToken('=', span(6, 7)),
+                LexerError::UnexpectedToken('@', span(10, 11)),
+                LexerError::UnterminatedString(span(12, 17)),
+            ]
+        );
+        let tokens: Vec<_> = tokens
+            .0
+            .iter()
+            .map(|token| (token.r#type, token.value.as_str()))
+            .zip(tokens.1)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                ((TokenType::Integer, "1"), span(0, 1)),
+                ((TokenType::Error, "$"), span(2, 3)),
+                ((TokenType::Integer, "2"), span(4, 5)),
+                ((TokenType::Error, "="), span(6, 7)),
+                ((TokenType::Integer, "3"), span(8, 9)),
+                ((TokenType::Error, "@"), span(10, 11)),
+                ((TokenType::Error, "open"), span(12, 17)),
+                ((TokenType::EOF, ""), span(17, 17)),
+            ]
+        );
+        let diagnostic = Diagnostic::from(&errors[3]);
+        assert!(diagnostic
+            .render(source)
+            .contains("2 | \"open\n  | ^^^^^ the string starts here and never ends\n"));
+        /* the strict lexer stops at the first error */
+        assert_eq!(
+            lexer.parse(source).err(),
+            Some(LexerError::UnexpectedToken('$', span(2, 3)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_runner() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        fn assert_send_sync<S: Send + Sync>(_: &S) {}
+        let runner = init_simple_script_parser()?;
+        assert_send_sync(&runner);
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let runner = &runner;
+                scope.spawn(move || {
+                    let mut env = RuntimeEnvironment::new();
+                    let script = format!("x = {} * (2 + 3)", i);
+                    let value = runner.run(&mut env, &script).ok().unwrap();
+                    assert_eq!(value.value(&env), &Value::Integer(i * 5));
+                });
+            }
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_reducer_argument_errors() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let token_map = lexer_token_map();
+        let operator = [TerminalSymbolDef("+", TokenType::Plus)];
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> + int", |mut args| args.nth_val(2)),
+            GrammarRule("S -> int", |mut args| {
+                args.expect_token()?;
+                args.expect_token().map(ASTNode::Token)
+            }),
+        ];
+        let runner = ScriptRunner::new(grammars, token_map, &operator, &[])?;
+        let mut env = RuntimeEnvironment::new();
+        assert!(matches!(
+            runner.run(&mut env, "+1"),
+            Err(ScriptError::Reducer(ReducerError::MissingArgument(2, 2)))
+        ));
+        assert!(matches!(
+            runner.run(&mut env, "1"),
+            Err(ScriptError::Reducer(ReducerError::MissingArgument(1, 1)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unexpected_node_errors() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let token_map = lexer_token_map();
+        let operator = [
+            TerminalSymbolDef("+", TokenType::Plus),
+            TerminalSymbolDef("-", TokenType::Minus),
+        ];
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            /* the expression of Lazy evaluates to another expression instead of a value */
+            GrammarRule("S -> + Lazy", |mut args| {
+                args.skip();
+                Ok(ASTNode::ActionExpression(
+                    "+lazy",
+                    Box::new(move |env| args.expect_value(env).map(ASTNode::Value)),
+                ))
+            }),
+            GrammarRule("Lazy -> int", |_| {
+                Ok(ASTNode::ActionExpression(
+                    "lazy",
+                    Box::new(|_| {
+                        Ok(ASTNode::ActionExpression(
+                            "int",
+                            Box::new(|_| Ok(ASTNode::Value(Value::Integer(1)))),
+                        ))
+                    }),
+                ))
+            }),
+            /* Num reduces to a value where a token is expected */
+            GrammarRule("S -> - Num", |mut args| {
+                args.skip();
+                args.expect_token().map(ASTNode::Token)
+            }),
+            GrammarRule("Num -> int", |mut args| {
+                Ok(ASTNode::Value(args.val()?.into_value()?))
+            }),
+        ];
+        let runner = ScriptRunner::new(grammars, token_map, &operator, &[])?;
+        let mut env = RuntimeEnvironment::new();
+        assert!(matches!(
+            runner.run(&mut env, "+1"),
+            Err(ScriptError::Reducer(ReducerError::UnexpectedNode(
+                "value",
+                "expression"
+            )))
+        ));
+        assert!(matches!(
+            runner.run(&mut env, "-1"),
+            Err(ScriptError::Reducer(ReducerError::UnexpectedNode(
+                "token", "value"
+            )))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_rule_children() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let token_map = lexer_token_map();
+        let operator = [TerminalSymbolDef("+", TokenType::Plus)];
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> lhs:int + rhs:int", |mut args| args.get("rhs")).labels(&["rhs"]),
+        ];
+        let runner = ScriptRunner::new(grammars, token_map, &operator, &[])?;
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "1+2")?, Value::Integer(2));
+
+        let token_map = lexer_token_map();
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> lhs:int + right:int", |mut args| args.get("rhs")).labels(&["rhs"]),
+        ];
+        /* declared labels are checked against the rule text when the runner is built */
+        assert!(matches!(
+            ScriptRunner::new(grammars, token_map, &operator, &[]),
+            Err(ScriptError::Reducer(ReducerError::UnknownLabel("rhs")))
+        ));
+
+        let token_map = lexer_token_map();
+        let grammars: Vec<GrammarRule<RuntimeEnvironment, TokenType, Value, ScriptRuntimeError>> = vec![
+            GrammarRule("B -> S EOF", never_reducer),
+            GrammarRule("S -> lhs:int + right:int", |mut args| args.get("rhs")),
+        ];
+        /* without declared labels a missing one is only found when the reducer runs */
+        let runner = ScriptRunner::new(grammars, token_map, &operator, &[])?;
+        assert!(matches!(
+            runner.run(&mut env, "1+2"),
+            Err(ScriptError::Reducer(ReducerError::UnknownLabel("rhs")))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiled_grammar() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_runner(TableSource::Compiled(&SIMPLE_SCRIPT_GRAMMAR))?;
+        /* #![allow_conflicts] lets the shifts win as in the table built at runtime */
+        let built = init_simple_script_parser()?;
+        assert!(runner.parse_table() == built.parse_table());
+        assert_eq!(
+            runner.lr_parser().conflicts(),
+            built.lr_parser().conflicts()
+        );
+        assert!(!built.lr_parser().conflicts().is_empty());
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "2*(3+4)")?, Value::Integer(14));
+        assert_eq!(runner.run(&mut env, "1+2*3")?, Value::Integer(7));
+        let value = runner.run(&mut env, "foo = -2.5 * 2")?;
+        assert_eq!(value.value(&env), &Value::Float(-5.0));
+        assert!(matches!(
+            runner.run(&mut env, "1 + * 2"),
+            Err(ScriptError::Syntax(SyntaxError::UnexpectedToken(_, _, _)))
+        ));
+
+        static MISMATCHED_GRAMMAR: CompiledGrammar = grammar! { "B -> S EOF", "S -> int" };
+        assert!(matches!(
+            init_runner(TableSource::Compiled(&MISMATCHED_GRAMMAR)),
+            Err(ScriptError::Grammar(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_grammar_conflicts() {
+        let tests = trybuild::TestCases::new();
+        tests.compile_fail("tests/compile_fail/grammar_conflicts.rs");
+    }
+
+    #[test]
+    fn test_saved_parse_table() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let table = init_simple_script_parser()?.parse_table();
+        let bytes = table.to_bytes();
+        let loaded = ParseTable::from_bytes(&bytes)?;
+        assert!(loaded == table);
+
+        let runner = init_runner(TableSource::Saved(&loaded))?;
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "(1+2)*3")?, Value::Integer(9));
+        assert_eq!(runner.run(&mut env, "-2 + 1.5")?, Value::Float(-0.5));
+
+        /* a table saved from an older version of the grammar */
+        let mut stale = loaded;
+        stale.fingerprint ^= 1;
+        assert!(matches!(
+            init_runner(TableSource::Saved(&stale)),
+            Err(ScriptError::Grammar(GrammarError::StaleParseTable))
+        ));
+        assert!(matches!(
+            ParseTable::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(GrammarError::InvalidParseTable(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_parse_table() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let table = runner.parse_table();
+        /* default reductions are saved instead of spelled out on every terminal */
+        assert_eq!(table.default_reduce.len(), table.table.len());
+        assert!(table.default_reduce.iter().any(Option::is_some));
+        /* the fingerprint covers the table construction as well as the grammar */
+        assert_ne!(
+            table.fingerprint,
+            runner.lr_parser().grammar_set.fingerprint()
+        );
+        let state_count = table.table.len();
+        let rejected = |edit: &dyn Fn(&mut ParseTable)| {
+            let mut broken = table.clone();
+            edit(&mut broken);
+            matches!(
+                init_runner(TableSource::Saved(&broken)),
+                Err(ScriptError::Grammar(GrammarError::InvalidParseTable(_)))
+            )
+        };
+        let action = |state: usize, name: &str| {
+            let row = &table.table[state];
+            row.iter().position(|(symbol, _)| symbol == name).unwrap()
+        };
+        let shift_int = action(0, "int");
+        assert!(rejected(
+            &|t| t.table[0][shift_int].1 = TransitionAction::Reduce(0)
+        ));
+        assert!(rejected(
+            &|t| t.table[0][shift_int].1 = TransitionAction::Reduce(19)
+        ));
+        assert!(rejected(&|t| {
+            t.table[0][shift_int].1 = TransitionAction::Shift(state_count)
+        }));
+        assert!(rejected(
+            &|t| t.table[0][shift_int].1 = TransitionAction::Goto(1)
+        ));
+        assert!(rejected(&|t| {
+            let duplicate = t.table[0][shift_int].clone();
+            t.table[0].push(duplicate);
+        }));
+        assert!(rejected(&|t| t.default_reduce[0] = Some(19)));
+        assert!(rejected(&|t| {
+            t.default_reduce.pop();
+        }));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parse_table_json() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let table = init_simple_script_parser()?.parse_table();
+        let json = serde_json::to_string(&table).unwrap();
+        let loaded: ParseTable = serde_json::from_str(&json).unwrap();
+        let runner = init_runner(TableSource::Saved(&loaded))?;
+        let mut env = RuntimeEnvironment::new();
+        assert_eq!(runner.run(&mut env, "2*3+4")?, Value::Integer(10));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_diagnostics_json() -> Result<(), ScriptError<ScriptRuntimeError>> {
+        let runner = init_simple_script_parser()?;
+        let mut env = RuntimeEnvironment::new();
+        let error = runner.run(&mut env, "(1 + 2").err().unwrap();
+        let json: serde_json::Value = serde_json::to_value(&error).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "code": "E0401",
+                "severity": "error",
+                "message": "unexpected token EOF",
+                "labels": [{
+                    "span": { "start": 6, "end": 6 },
+                    "message": "unexpected here",
+                    "primary": true,
+                }],
+                "notes": [],
+                "help": "expected one of Plus, RightParenthese",
+                "expected": ["Plus", "RightParenthese"],
+            })
+        );
+        let error = runner.run(&mut env, "1 + x").err().unwrap();
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "E0600");
+        assert_eq!(
+            json["labels"],
+            serde_json::json!([{
+                "span": { "start": 0, "end": 5 },
+                "message": "in `a + b`",
+                "primary": true,
+            }])
+        );
         Ok(())
     }
 }
@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod typed_ast_tests {
+    use std::collections::HashMap;
+
+    use ry_script::{
+        ast::RuntimeValue,
+        error::{RuntimeError, ScriptError},
+        grammar::TerminalSymbolDef,
+        runner::ScriptRunner,
+        token::{LexerTokenMap, ParserToken, Token},
+    };
+    use ry_script_derive::TypedAst;
+
+    #[allow(clippy::upper_case_acronyms)]
+    #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+    enum TokenType {
+        Identifier,
+        Assignment,
+        Integer,
+        Float,
+        String,
+        Plus,
+        Minus,
+        Multiply,
+        LeftParenthese,
+        RightParenthese,
+        EOF,
+    }
+
+    impl ParserToken<TokenType> for TokenType {
+        fn entity(self, value: String) -> Token<TokenType> {
+            Token {
+                r#type: self,
+                value,
+            }
+        }
+    }
+
+    struct UnknownVariable(String);
+
+    impl RuntimeError for UnknownVariable {}
+
+    impl std::fmt::Display for UnknownVariable {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Unknown variable {}", self.0)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Ident(String);
+
+    impl From<Token<TokenType>> for Ident {
+        fn from(token: Token<TokenType>) -> Self {
+            Ident(token.value)
+        }
+    }
+
+    /* The typed AST, every variant is produced by the rules it is annotated with */
+    #[derive(Debug, TypedAst)]
+    #[ast(token = TokenType, start = "B -> S EOF")]
+    #[rule("S -> A1")]
+    #[rule("A1 -> A2")]
+    #[rule("A2 -> Val")]
+    #[rule("Val -> ( A1 )")]
+    enum Expr {
+        #[rule("S -> id = A1")]
+        Assign(Ident, Box<Expr>),
+        #[rule("A1 -> A1 + A2")]
+        Add(Box<Expr>, Box<Expr>),
+        #[rule("A2 -> lhs:A2 * rhs:Val")]
+        Mul { lhs: Box<Expr>, rhs: Box<Expr> },
+        #[rule("Val -> - Val")]
+        Neg(Box<Expr>),
+        #[rule("Val -> id")]
+        #[token(TokenType::Identifier)]
+        Var(Ident),
+        #[rule("Val -> int")]
+        #[token(TokenType::Integer)]
+        Int(Token<TokenType>),
+        /* operators are never a value on their own */
+        #[token(_)]
+        Unexpected(Token<TokenType>),
+    }
+
+    impl RuntimeValue<TokenType> for Expr {}
+
+    impl std::fmt::Display for Expr {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    /* Evaluation is plain Rust over the typed AST */
+    impl Expr {
+        fn eval(&self, env: &mut HashMap<String, i64>) -> Result<i64, UnknownVariable> {
+            match self {
+                Expr::Assign(Ident(name), rhs) => {
+                    let val = rhs.eval(env)?;
+                    env.insert(name.clone(), val);
+                    Ok(val)
+                }
+                Expr::Add(lhs, rhs) => Ok(lhs.eval(env)? + rhs.eval(env)?),
+                Expr::Mul { lhs, rhs } => Ok(lhs.eval(env)? * rhs.eval(env)?),
+                Expr::Neg(val) => Ok(-val.eval(env)?),
+                Expr::Var(Ident(name)) => match env.get(name) {
+                    Some(val) => Ok(*val),
+                    None => Err(UnknownVariable(name.clone())),
+                },
+                Expr::Int(token) => Ok(token.value.parse().unwrap()),
+                Expr::Unexpected(token) => panic!("{} is not an expression", token.value),
+            }
+        }
+    }
+
+    fn init_typed_parser(
+    ) -> ry_script::error::Result<ScriptRunner<(), TokenType, Expr, UnknownVariable>, UnknownVariable>
+    {
+        let token_map = LexerTokenMap::new(
+            TokenType::EOF,
+            TokenType::Identifier,
+            TokenType::Integer,
+            TokenType::Float,
+            TokenType::String,
+        );
+        let operator = [
+            TerminalSymbolDef("=", TokenType::Assignment),
+            TerminalSymbolDef("+", TokenType::Plus),
+            TerminalSymbolDef("-", TokenType::Minus),
+            TerminalSymbolDef("*", TokenType::Multiply),
+            TerminalSymbolDef("(", TokenType::LeftParenthese),
+            TerminalSymbolDef(")", TokenType::RightParenthese),
+        ];
+        ScriptRunner::new(Expr::grammar_rules(), token_map, &operator, &[])
+    }
+
+    #[test]
+    fn test_typed_ast() -> Result<(), ScriptError<UnknownVariable>> {
+        let runner = init_typed_parser()?;
+        let mut env = HashMap::new();
+        let ast = runner.run(&mut (), "x = 2 * (3 + -4)")?;
+        assert!(matches!(&ast, Expr::Assign(Ident(name), _) if name == "x"));
+        assert_eq!(ast.eval(&mut env)?, -2);
+        let ast = runner.run(&mut (), "x * x + 1")?;
+        assert_eq!(ast.eval(&mut env)?, 5);
+        assert!(runner.run(&mut (), "y + 1")?.eval(&mut env).is_err());
+        /* tokens convert to the variant whose #[token] pattern matches their type first */
+        let expr = Expr::from(TokenType::Identifier.entity("x".to_string()));
+        assert_eq!(expr.eval(&mut env)?, -2);
+        let expr = Expr::from(TokenType::Integer.entity("3".to_string()));
+        assert_eq!(expr.eval(&mut env)?, 3);
+        let expr = Expr::from(TokenType::Plus.entity("+".to_string()));
+        assert!(matches!(expr, Expr::Unexpected(_)));
+        Ok(())
+    }
+}